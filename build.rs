@@ -1,6 +1,14 @@
 extern crate gcc;
 
+use std::env;
+
 fn main() {
+    // Thread-local working storage lets nauty run on several threads at once.
+    let tls = if env::var("TARGET").unwrap().contains("msvc") {
+        "__declspec(thread)"
+    } else {
+        "__thread"
+    };
     gcc::Config::new()
         .file("nauty/nauty.c")
         .file("nauty/nautil.c")
@@ -17,6 +25,9 @@ fn main() {
         .file("nauty/gtnauty.c")
         .file("nauty/naugroup.c")
         .define("WORDSIZE", Some("64"))
-        .define("MAXN", Some("WORDSIZE"))
+        // Allocate working storage by graph size, so graphs of any size can be canonicalized.
+        .define("MAXN", Some("0"))
+        .define("HAVE_TLS", Some("1"))
+        .define("TLS_ATTR", Some(tls))
         .compile("libnautyL1.a");
 }
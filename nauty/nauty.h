@@ -31,8 +31,12 @@ it is necessary to check they are correct.
 
 #define HAVE_CONST 1    /* compiler properly supports const */
 
+#ifndef HAVE_TLS
 #define HAVE_TLS 0   /* have storage attribute for thread-local */
+#endif
+#ifndef TLS_ATTR
 #define TLS_ATTR   /* if so, what it is.  if not, empty */
+#endif
 
 #define USE_ANSICONTROLS 0
                           /* whether --enable-ansicontrols is used */
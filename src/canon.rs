@@ -0,0 +1,195 @@
+use error::*;
+use motifs::*;
+use nauty::*;
+use network::*;
+
+use std::collections::HashMap;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::mem;
+
+/// The `MotifId` of the subgraph induced by `nodes`, in the order given rather than canonical
+/// order, with `bits` wide digits.
+pub fn raw_id<D: Directedness>(net: &Network<D>, nodes: &[NodeIndex], bits: usize) -> Result<MotifId> {
+    if let Some(u) = nodes.iter().find(|u| u.index() >= net.node_count()) {
+        return Err(Error::NoSuchNode(*u));
+    }
+    let n = nodes.len();
+    let mut id = MotifId::new();
+    for (i, u) in nodes.iter().enumerate() {
+        for (j, v) in nodes.iter().enumerate() {
+            let pos = match digit_position(n, i, j, D::is_directed()) {
+                Some(pos) => pos,
+                None => continue,
+            };
+            if let Some(e) = net.find_edge(*u, *v) {
+                if net[e] == 0 || (net[e] as u64) >> bits != 0 {
+                    return Err(Error::InvalidEdgeType(net[e]));
+                }
+                id.set_digit(pos, bits, net[e] as u64);
+            }
+        }
+    }
+    Ok(id)
+}
+
+/// Memoizes canonicalization of k-node subgraphs by their raw adjacency code, so that nauty only
+/// runs once for each labelled adjacency pattern. The codes and IDs are of `Directed` or
+/// `Undirected` motifs, as `D` says.
+#[derive(Debug)]
+pub struct CanonCache<D = Directed> {
+    k: usize,
+    bits: usize,
+    table: HashMap<MotifId, MotifId>,
+    // Only a marker, so `Send`, `Sync` and `Clone` whatever `D` is.
+    directedness: PhantomData<fn() -> D>,
+}
+
+impl<D> Clone for CanonCache<D> {
+    fn clone(&self) -> CanonCache<D> {
+        CanonCache { k: self.k, bits: self.bits, table: self.table.clone(), directedness: PhantomData }
+    }
+}
+
+impl<D: Directedness> CanonCache<D> {
+    /// A cache for k-node subgraphs with `bits` wide `MotifId` digits, as from `digit_bits`.
+    pub fn new(k: usize, bits: usize) -> Result<CanonCache<D>> {
+        try!(check_size(k));
+        if bits < MIN_DIGIT_BITS || bits > 32 || !bits.is_power_of_two() {
+            return Err(Error::InvalidDigitBits(bits));
+        }
+        Ok(CanonCache { k: k, bits: bits, table: HashMap::new(), directedness: PhantomData })
+    }
+
+    /// A cache filled with every labelled adjacency pattern over `edge_types`, so it never
+    /// misses on networks using only those types.
+    ///
+    /// There are `(edge_types.len() + 1)^(k * (k - 1))` directed patterns: 4096 for `k = 4` with
+    /// one edge type, but 16 million for `k = 4` with all three. Undirected motifs have half as
+    /// many cells. Only `k <= 4` is supported.
+    pub fn with_table(k: usize, edge_types: &[EdgeType]) -> Result<CanonCache<D>> {
+        if k > 4 {
+            return Err(Error::InvalidSize { k: k, max: 4 });
+        }
+        if let Some(w) = edge_types.iter().find(|w| **w == 0) {
+            return Err(Error::InvalidEdgeType(*w));
+        }
+        let all = edge_types.iter().fold(0, |all, w| all | w);
+        let bits = digit_bits(mem::size_of::<EdgeType>() * 8 - all.leading_zeros() as usize);
+        let mut cache = try!(CanonCache::new(k, bits));
+        let cells = if D::is_directed() {
+            Vec::from_iter((0..k * k).filter(|pos| pos / k != pos % k))
+        } else {
+            Vec::from_iter(0..adjacency_digits(k, false))
+        };
+        let mut digits = vec![0; cells.len()];
+        loop {
+            let mut raw = MotifId::new();
+            for (pos, d) in cells.iter().zip(&digits) {
+                if *d > 0 {
+                    raw.set_digit(*pos, bits, edge_types[*d - 1] as u64);
+                }
+            }
+            cache.get(raw);
+
+            // Count through every combination of digits, like an odometer.
+            match digits.iter().position(|d| *d < edge_types.len()) {
+                Some(i) => {
+                    digits[i] += 1;
+                    for d in &mut digits[..i] {
+                        *d = 0;
+                    }
+                }
+                None => return Ok(cache),
+            }
+        }
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// The width of the `MotifId` digits.
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// The number of adjacency patterns seen so far.
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// The canonical `MotifId` of the k-node subgraph with adjacency code `raw`, followed by its
+    /// node colours if it has any.
+    pub fn get(&mut self, raw: MotifId) -> MotifId {
+        let (k, bits) = (self.k, self.bits);
+        self.table.entry(raw)
+            .or_insert_with_key(|raw| {
+                // `new` checked k, and a `MotifId`'s digits are all valid edge types and colours.
+                let (motif, colours) = canonicalize_coloured(id_to_network::<D>(k, bits, raw),
+                                                             &id_node_colours::<D>(k, bits, raw))
+                    .expect("k was checked");
+                coloured_motif_id(&motif, &colours, bits).expect("digits are edge types")
+            })
+            .clone()
+    }
+
+    /// The canonical `MotifId` of the subgraph induced by `nodes`.
+    pub fn canonical_id(&mut self, net: &Network<D>, nodes: &[NodeIndex]) -> Result<MotifId> {
+        debug_assert_eq!(self.k, nodes.len());
+        Ok(self.get(try!(raw_id(net, nodes, self.bits))))
+    }
+}
+
+#[test]
+fn test_canon_cache() {
+    let net = network_from_paper();
+    let mut cache = CanonCache::new(3, 2).unwrap();
+    let n = net.node_count();
+    for a in 0..n {
+        for b in 0..n {
+            for c in 0..n {
+                if a != b && b != c && a != c {
+                    let nodes = [NodeIndex::new(a), NodeIndex::new(b), NodeIndex::new(c)];
+                    let expected = motif_id(&canonicalize(net.subnet(&nodes).unwrap()).unwrap(), 2).unwrap();
+                    assert_eq!(expected, cache.canonical_id(&net, &nodes).unwrap());
+                }
+            }
+        }
+    }
+    // Far fewer patterns than node triples.
+    assert!(cache.len() < 64);
+
+    assert!(cache.canonical_id(&net, &[NodeIndex::new(0), NodeIndex::new(1), NodeIndex::new(16)]).is_err());
+    assert!(CanonCache::<Directed>::new(0, 2).is_err());
+}
+
+#[test]
+fn test_canon_cache_table() {
+    let table: CanonCache = CanonCache::with_table(3, &[1, 2, 3]).unwrap();
+    assert_eq!(4096, table.len());
+    let mut cache: CanonCache = CanonCache::new(3, 2).unwrap();
+    for (raw, id) in &table.table {
+        assert_eq!(*id, cache.get(raw.clone()));
+    }
+
+    let table: CanonCache = CanonCache::with_table(4, &[1]).unwrap();
+    assert_eq!(4096, table.len());
+    // 218 directed graphs on 4 nodes, up to isomorphism.
+    let mut ids = Vec::from_iter(table.table.values());
+    ids.sort();
+    ids.dedup();
+    assert_eq!(218, ids.len());
+
+    // And 11 undirected graphs, from 2^6 patterns.
+    let table = CanonCache::<Undirected>::with_table(4, &[1]).unwrap();
+    assert_eq!(64, table.len());
+    let mut ids = Vec::from_iter(table.table.values());
+    ids.sort();
+    ids.dedup();
+    assert_eq!(11, ids.len());
+}
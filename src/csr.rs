@@ -0,0 +1,108 @@
+use network::*;
+
+/// A compressed sparse row copy of a `Network`'s adjacency, for the enumeration hot paths.
+///
+/// Nodes are numbered by their `NodeIndex`. Each node has a sorted list of its neighbours
+/// ignoring direction and self-loops, and a sorted list of its out-neighbours with the `EdgeType`
+/// of each edge. The edges of undirected networks go out both ways.
+#[derive(Clone, Debug)]
+pub struct Csr {
+    offsets: Vec<usize>,
+    neighbours: Vec<u32>,
+    out_offsets: Vec<usize>,
+    out: Vec<(u32, EdgeType)>,
+}
+
+impl Csr {
+    pub fn new<D: Directedness>(net: &Network<D>) -> Csr {
+        let n = net.node_count();
+        let mut undirected = vec![Vec::new(); n];
+        let mut out = vec![Vec::new(); n];
+        for e in net.raw_edges() {
+            let (u, v) = (e.source().index(), e.target().index());
+            if u != v {
+                undirected[u].push(v as u32);
+                undirected[v].push(u as u32);
+                if !D::is_directed() {
+                    out[v].push((u as u32, e.weight));
+                }
+            }
+            out[u].push((v as u32, e.weight));
+        }
+
+        let mut csr = Csr {
+            offsets: vec![0],
+            neighbours: Vec::with_capacity(2 * net.edge_count()),
+            out_offsets: vec![0],
+            out: Vec::with_capacity(net.edge_count()),
+        };
+        for (mut us, mut vs) in undirected.into_iter().zip(out) {
+            us.sort();
+            us.dedup();
+            csr.neighbours.extend(us);
+            csr.offsets.push(csr.neighbours.len());
+
+            // Parallel edges merge their types, like `motif_id` does.
+            vs.sort();
+            let start = csr.out.len();
+            for (v, w) in vs {
+                if csr.out.len() > start && csr.out[csr.out.len() - 1].0 == v {
+                    csr.out.last_mut().unwrap().1 |= w;
+                } else {
+                    csr.out.push((v, w));
+                }
+            }
+            csr.out_offsets.push(csr.out.len());
+        }
+        csr
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// The neighbours of `v` in either direction, in increasing order.
+    pub fn neighbours(&self, v: usize) -> &[u32] {
+        &self.neighbours[self.offsets[v]..self.offsets[v + 1]]
+    }
+
+    pub fn degree(&self, v: usize) -> usize {
+        self.offsets[v + 1] - self.offsets[v]
+    }
+
+    /// The out-neighbours of `v` and their `EdgeType`s, in increasing order.
+    pub fn out_edges(&self, v: usize) -> &[(u32, EdgeType)] {
+        &self.out[self.out_offsets[v]..self.out_offsets[v + 1]]
+    }
+
+    /// The `EdgeType` of `u -> v`, or 0 if there is no such edge.
+    pub fn edge(&self, u: usize, v: usize) -> EdgeType {
+        let out = self.out_edges(u);
+        match out.binary_search_by(|e| e.0.cmp(&(v as u32))) {
+            Ok(i) => out[i].1,
+            Err(_) => 0,
+        }
+    }
+}
+
+#[test]
+fn test_csr() {
+    let mut net = network_from_paper();
+    let (a, b) = (NodeIndex::new(0), NodeIndex::new(1));
+    net.add_edge(a, b, 2);
+    net.add_edge(a, a, 1);
+    let csr = Csr::new(&net);
+    assert_eq!(16, csr.node_count());
+    // 1 -> 16, 2 -> 1, 8 -> 1 and the new 1 -> 2; the self-loop is not a neighbour.
+    assert_eq!(&[1, 7, 15], csr.neighbours(0));
+    assert_eq!(&[(0, 1), (1, 2), (15, 1)], csr.out_edges(0));
+    // 2 -> 1 and 1 -> 2 are both there, the second as a repression.
+    assert_eq!(1, csr.edge(1, 0));
+    assert_eq!(2, csr.edge(0, 1));
+    assert_eq!(0, csr.edge(0, 2));
+    assert_eq!(1, csr.edge(0, 0));
+    assert_eq!(4, csr.degree(9));
+
+    net.add_edge(a, b, 1);
+    assert_eq!(3, Csr::new(&net).edge(0, 1));
+}
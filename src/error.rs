@@ -0,0 +1,88 @@
+use motifs::{MotifId, ParseMotifIdError};
+use network::*;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+/// Everything that can go wrong in rustmotifs.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// Malformed input, with the line it is on if it came from a file.
+    Parse { line: Option<usize>, msg: String },
+    /// A motif size outside `1..=max`.
+    InvalidSize { k: usize, max: usize },
+    /// A network too large for nauty to canonicalize.
+    TooManyNodes { nodes: usize, max: usize },
+    /// An edge weight of 0, or one with more colours than the `MotifId` digits hold.
+    InvalidEdgeType(EdgeType),
+    /// A `MotifId` digit width that is not a power of two from 2 to 32 bits.
+    InvalidDigitBits(usize),
+    /// A node index that is not in the network.
+    NoSuchNode(NodeIndex),
+    /// A motif that is not among the ones counted.
+    NoSuchMotif(MotifId),
+    /// Node colours given for some nodes of a network but not all.
+    NodeColourCount { nodes: usize, colours: usize },
+    /// A node colour too large for the `MotifId` digits.
+    InvalidNodeColour(NodeColour),
+    /// RAND-ESU needs one probability in `(0, 1]` per depth.
+    InvalidProbabilities(Vec<f64>),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    /// A `Parse` error on line `lineno`, counting from 0.
+    pub fn parse_at(lineno: usize, msg: String) -> Error {
+        Error::Parse { line: Some(lineno + 1), msg: msg }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => e.fmt(f),
+            Error::Parse { line: Some(line), ref msg } => write!(f, "line {}: {}", line, msg),
+            Error::Parse { line: None, ref msg } => f.write_str(msg),
+            Error::InvalidSize { k, max } => write!(f, "motif size must be between 1 and {}, not {}", max, k),
+            Error::TooManyNodes { nodes, max } => {
+                write!(f, "too many nodes to canonicalize: {}, at most {} with these edge types", nodes, max)
+            }
+            Error::InvalidEdgeType(w) => write!(f, "invalid edge type: {}", w),
+            Error::InvalidDigitBits(bits) => write!(f, "invalid motif id digit width: {} bits", bits),
+            Error::NoSuchNode(n) => write!(f, "no such node: {}", n.index()),
+            Error::NoSuchMotif(ref m) => write!(f, "no such motif: {}", m),
+            Error::NodeColourCount { nodes, colours } => {
+                write!(f, "need one colour per node, got {} colours for {} nodes", colours, nodes)
+            }
+            Error::InvalidNodeColour(c) => write!(f, "invalid node colour: {}", c),
+            Error::InvalidProbabilities(ref p) => {
+                write!(f, "need one sampling probability in (0, 1] per depth, got {:?}", p)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<ParseMotifIdError> for Error {
+    fn from(e: ParseMotifIdError) -> Error {
+        Error::Parse { line: None, msg: e.to_string() }
+    }
+}
@@ -0,0 +1,285 @@
+use canon::*;
+use error::*;
+use motifs::*;
+use network::*;
+
+use std::collections::{BTreeMap, BTreeSet};
+#[cfg(test)]
+use std::iter::FromIterator;
+
+/// The subgraph-inclusion matrix between k-node motifs, for turning induced counts into
+/// non-induced ones.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InclusionMatrix {
+    /// `contains[m][h]` is the number of distinct spanning subgraphs of motif `m` that are copies
+    /// of the connected motif `h`, where that is not 0. Every motif contains itself once.
+    ///
+    /// A subgraph keeps some of the edges, and of each edge some of its colours.
+    pub contains: BTreeMap<MotifId, BTreeMap<MotifId, usize>>,
+}
+
+impl InclusionMatrix {
+    /// The number of copies of each motif in a network, induced or not, from its induced
+    /// `enumerate_subgraphs` counts. Each k-node subgraph counts towards its own motif and every
+    /// motif it contains.
+    ///
+    /// Fails if a motif of `induced` is not a row of the matrix.
+    pub fn non_induced(&self, induced: &MotifFreq) -> Result<MotifFreq> {
+        let mut out = MotifFreq::new();
+        for (m, count) in induced {
+            let row = try!(self.contains.get(m).ok_or_else(|| Error::NoSuchMotif(m.clone())));
+            for (h, c) in row {
+                *out.entry(h.clone()).or_insert(0) += c * count;
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// The rows of the inclusion matrix for `motifs`, canonical k-node motifs as `cache` gives them.
+///
+/// A motif's row is found by taking its edge colours off one at a time, and counting how many
+/// ways each connected motif reached fits into it. That is up to `k!` steps for each pair, so it
+/// is meant for the small k that motifs are counted at.
+pub fn inclusion_matrix<'a, D, I>(motifs: I, cache: &mut CanonCache<D>) -> InclusionMatrix
+    where D: Directedness,
+          I: IntoIterator<Item = &'a MotifId>
+{
+    let mut matrix = InclusionMatrix::default();
+    for m in motifs {
+        if !matrix.contains.contains_key(m) {
+            let row = inclusion_row(m, cache);
+            matrix.contains.insert(m.clone(), row);
+        }
+    }
+    matrix
+}
+
+fn inclusion_row<D: Directedness>(m: &MotifId, cache: &mut CanonCache<D>) -> BTreeMap<MotifId, usize> {
+    let (k, bits) = (cache.k(), cache.bits());
+    let whole = Pattern::new::<D>(k, bits, m);
+    let mut row = BTreeMap::new();
+    let mut seen = BTreeSet::new();
+    seen.insert(m.clone());
+    let mut todo = vec![m.clone()];
+    while let Some(h) = todo.pop() {
+        let part = Pattern::new::<D>(k, bits, &h);
+        // Taking more edges away never reconnects it.
+        if !part.is_connected() {
+            continue;
+        }
+        row.insert(h.clone(), part.embeddings(&whole) / part.embeddings(&part));
+        for pos in 0..adjacency_digits(k, D::is_directed()) {
+            let digit = h.digit(pos, bits);
+            for b in (0..bits).filter(|b| digit & 1 << b != 0) {
+                let mut raw = h.clone();
+                raw.set_digit(pos, bits, digit & !(1 << b));
+                let sub = cache.get(raw);
+                if seen.insert(sub.clone()) {
+                    todo.push(sub);
+                }
+            }
+        }
+    }
+    row
+}
+
+/// The non-induced counts of the motifs in `induced`, and of every motif they contain.
+pub fn non_induced_counts<D: Directedness>(induced: &MotifFreq, cache: &mut CanonCache<D>) -> MotifFreq {
+    inclusion_matrix(induced.keys(), cache).non_induced(induced).expect("a row for every induced motif")
+}
+
+/// Counts the copies of each k-node motif in `net`, not just as induced subgraphs: a feedforward
+/// loop with a back-edge between two of its nodes still counts as a feedforward loop, as well as
+/// the motif it induces.
+pub fn enumerate_non_induced_subgraphs<D: Directedness>(k: usize, net: &Network<D>) -> Result<MotifFreq> {
+    let mut cache = try!(CanonCache::new(k, digit_bits(colour_count(net))));
+    let induced = try!(enumerate_subgraphs_with(net, &[], &mut cache));
+    Ok(non_induced_counts(&induced, &mut cache))
+}
+
+/// A k-node motif as a k-by-k matrix of `EdgeType`s, symmetric if it is undirected, and its node
+/// colours.
+struct Pattern {
+    k: usize,
+    adj: Vec<EdgeType>,
+    colours: Vec<NodeColour>,
+}
+
+impl Pattern {
+    fn new<D: Directedness>(k: usize, bits: usize, id: &MotifId) -> Pattern {
+        let mut adj = vec![0; k * k];
+        for i in 0..k {
+            for j in 0..k {
+                if let Some(pos) = digit_position(k, i, j, D::is_directed()) {
+                    adj[k * i + j] = id.digit(pos, bits) as EdgeType;
+                }
+            }
+        }
+        Pattern { k: k, adj: adj, colours: id_node_colours::<D>(k, bits, id) }
+    }
+
+    fn is_connected(&self) -> bool {
+        let k = self.k;
+        let mut reached = vec![false; k];
+        let mut todo = vec![0];
+        while let Some(i) = todo.pop() {
+            if i < k && !reached[i] {
+                reached[i] = true;
+                todo.extend((0..k).filter(|j| self.adj[k * i + j] | self.adj[k * j + i] != 0));
+            }
+        }
+        reached.iter().all(|r| *r)
+    }
+
+    /// The number of ways to put this pattern's nodes onto `other`'s, keeping node colours, so
+    /// that every edge lands on an edge with at least its colours.
+    fn embeddings(&self, other: &Pattern) -> usize {
+        self.extend(other, &mut Vec::with_capacity(self.k), &mut vec![false; self.k])
+    }
+
+    fn extend(&self, other: &Pattern, image: &mut Vec<usize>, used: &mut [bool]) -> usize {
+        let (k, i) = (self.k, image.len());
+        if i == k {
+            return 1;
+        }
+        let fits = |a: usize, b: usize, x: usize, y: usize| self.adj[k * a + b] & !other.adj[k * x + y] == 0;
+        let mut total = 0;
+        for v in 0..k {
+            if used[v] || self.colours[i] != other.colours[v] || !fits(i, i, v, v) {
+                continue;
+            }
+            if (0..i).all(|j| fits(i, j, v, image[j]) && fits(j, i, image[j], v)) {
+                used[v] = true;
+                image.push(v);
+                total += self.extend(other, image, used);
+                image.pop();
+                used[v] = false;
+            }
+        }
+        total
+    }
+}
+
+#[cfg(test)]
+fn brute_force_non_induced<D: Directedness>(k: usize, net: &Network<D>) -> MotifFreq {
+    use petgraph::algo::connected_components;
+
+    // Every k-node set, and every way to keep some of the colours of the edges between them.
+    let bits = digit_bits(colour_count(net));
+    let mut cache = CanonCache::<D>::new(k, bits).unwrap();
+    let n = net.node_count();
+    let mut out = MotifFreq::new();
+    let mut nodes: Vec<_> = (0..k).collect();
+    loop {
+        let raw = raw_id(net, &Vec::from_iter(nodes.iter().map(|v| NodeIndex::new(*v))), bits).unwrap();
+        let cells = Vec::from_iter((0..adjacency_digits(k, D::is_directed())).filter(|p| raw.digit(*p, bits) != 0));
+        let mut kept = Vec::from_iter(cells.iter().map(|p| raw.digit(*p, bits)));
+        loop {
+            let mut sub = raw.clone();
+            for (p, d) in cells.iter().zip(&kept) {
+                sub.set_digit(*p, bits, *d);
+            }
+            let id = cache.get(sub);
+            if connected_components(&id_to_network::<D>(k, bits, &id)) == 1 {
+                *out.entry(id).or_insert(0) += 1;
+            }
+            // Count down through the submasks of each digit, like an odometer.
+            match (0..cells.len()).find(|i| kept[*i] != 0) {
+                Some(i) => {
+                    kept[i] = (kept[i] - 1) & raw.digit(cells[i], bits);
+                    for j in 0..i {
+                        kept[j] = raw.digit(cells[j], bits);
+                    }
+                }
+                None => break,
+            }
+        }
+        // The next k-node set, in lexicographic order.
+        match (0..k).rev().find(|i| nodes[*i] < n - k + i) {
+            Some(i) => {
+                nodes[i] += 1;
+                for j in i + 1..k {
+                    nodes[j] = nodes[j - 1] + 1;
+                }
+            }
+            None => return out,
+        }
+    }
+}
+
+#[test]
+fn test_feedforward_with_back_edge() {
+    let mut net = Network::new();
+    let (a, b, c) = (net.add_node("a".to_string()), net.add_node("b".to_string()), net.add_node("c".to_string()));
+    net.add_edge(a, b, 1);
+    net.add_edge(b, c, 1);
+    net.add_edge(a, c, 1);
+    let ffl = motif_id(&::nauty::canonicalize(net.clone()).unwrap(), 2).unwrap();
+    net.add_edge(c, a, 1);
+
+    let induced = enumerate_subgraphs(3, &net).unwrap();
+    assert_eq!(None, induced.get(&ffl));
+    let non_induced = enumerate_non_induced_subgraphs(3, &net).unwrap();
+    assert_eq!(Some(&1), non_induced.get(&ffl));
+    // Five motifs with three or four edges, and two-edge motifs: both fans once and three chains.
+    assert_eq!(vec![1, 1, 1, 1, 1, 1, 1, 3], {
+        let mut counts = Vec::from_iter(non_induced.values().cloned());
+        counts.sort();
+        counts
+    });
+    assert_eq!(non_induced, brute_force_non_induced(3, &net));
+}
+
+#[test]
+fn test_non_induced_brute_force() {
+    use randomize::Rng;
+
+    let mut rng = Rng::new(12);
+    for round in 0..6 {
+        let n = 7;
+        let mut net = Network::new();
+        for i in 0..n {
+            net.add_node(format!("{}", i));
+        }
+        for _ in 0..2 * n {
+            let (u, v) = (NodeIndex::new(rng.below(n)), NodeIndex::new(rng.below(n)));
+            // Two colours in some rounds, including dual edges.
+            let w = if round % 2 == 0 { 1 } else { 1 + rng.below(3) as EdgeType };
+            if u != v && net.find_edge(u, v).is_none() {
+                net.add_edge(u, v, w);
+            }
+        }
+        for k in 2..5 {
+            assert_eq!(brute_force_non_induced(k, &net), enumerate_non_induced_subgraphs(k, &net).unwrap());
+        }
+        let net = with_directedness::<Undirected>(&net);
+        for k in 2..5 {
+            assert_eq!(brute_force_non_induced(k, &net), enumerate_non_induced_subgraphs(k, &net).unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_inclusion_matrix_coloured() {
+    // A triangle of two TFs and a gene contains three paths, two with a TF in the middle.
+    let mut net = Network::new_undirected();
+    let nodes = Vec::from_iter((0..3).map(|i| net.add_node(format!("{}", i))));
+    net.add_edge(nodes[0], nodes[1], 1);
+    net.add_edge(nodes[1], nodes[2], 1);
+    net.add_edge(nodes[0], nodes[2], 1);
+    let colours = [1, 1, 2];
+    let mut cache = CanonCache::new(3, 2).unwrap();
+    let induced = enumerate_subgraphs_with(&net, &colours, &mut cache).unwrap();
+    let matrix = inclusion_matrix(induced.keys(), &mut cache);
+    let row = &matrix.contains[induced.keys().next().unwrap()];
+    assert_eq!(3, row.len());
+    let mut counts = Vec::from_iter(row.values().cloned());
+    counts.sort();
+    assert_eq!(vec![1, 1, 2], counts);
+    assert_eq!(4, matrix.non_induced(&induced).unwrap().values().sum::<usize>());
+    match InclusionMatrix::default().non_induced(&induced) {
+        Err(Error::NoSuchMotif(ref m)) => assert_eq!(induced.keys().next(), Some(m)),
+        r => panic!("expected NoSuchMotif, got {:?}", r),
+    }
+}
@@ -0,0 +1,267 @@
+use canon::*;
+use error::*;
+use motifs::*;
+use network::*;
+
+use std::collections::HashSet;
+use std::iter::FromIterator;
+
+/// Motif counts for a network that is being edited, kept up to date edit by edit.
+///
+/// An edit between `u` and `v` can only change the k-subgraphs with both of them, so only those
+/// are enumerated again: each is taken off the counts as it was, and put back as it is now.
+#[derive(Clone, Debug)]
+pub struct IncrementalCounter<D: Directedness = Directed> {
+    net: Network<D>,
+    cache: CanonCache<D>,
+    counts: MotifFreq,
+}
+
+impl<D: Directedness> IncrementalCounter<D> {
+    /// Counts the k-subgraphs of `net`, as `enumerate_subgraphs` does. Edges between the same
+    /// nodes are merged into one with the colours of all of them.
+    pub fn new(k: usize, net: Network<D>) -> Result<IncrementalCounter<D>> {
+        let bits = digit_bits(colour_count(&net));
+        IncrementalCounter::with_bits(k, net, bits)
+    }
+
+    /// `new`, but with `MotifId` digits `bits` wide, to leave room for edge types that `net`
+    /// does not use yet.
+    pub fn with_bits(k: usize, net: Network<D>, bits: usize) -> Result<IncrementalCounter<D>> {
+        let mut cache = try!(CanonCache::new(k, bits));
+        let net = merge_parallel_edges(&net);
+        let counts = try!(enumerate_subgraphs_with(&net, &[], &mut cache));
+        Ok(IncrementalCounter { net: net, cache: cache, counts: counts })
+    }
+
+    pub fn network(&self) -> &Network<D> {
+        &self.net
+    }
+
+    /// The number of k-subgraphs of the network as it is now, by motif.
+    pub fn counts(&self) -> &MotifFreq {
+        &self.counts
+    }
+
+    /// Adds an edge `u -> v`, or adds `w`'s colours to the one already there.
+    pub fn add_edge(&mut self, u: NodeIndex, v: NodeIndex, w: EdgeType) -> Result<()> {
+        let old = self.net.find_edge(u, v).map_or(0, |e| self.net[e]);
+        self.update(u, v, Some(old | w)).map(|_| ())
+    }
+
+    /// Removes the edge `u -> v`, giving its type if there was one.
+    pub fn remove_edge(&mut self, u: NodeIndex, v: NodeIndex) -> Result<Option<EdgeType>> {
+        self.update(u, v, None)
+    }
+
+    /// Makes `w` the type of `u -> v`, adding the edge if there is none. Gives the old type.
+    pub fn set_edge_type(&mut self, u: NodeIndex, v: NodeIndex, w: EdgeType) -> Result<Option<EdgeType>> {
+        self.update(u, v, Some(w))
+    }
+
+    fn update(&mut self, u: NodeIndex, v: NodeIndex, w: Option<EdgeType>) -> Result<Option<EdgeType>> {
+        if let Some(x) = [u, v].iter().find(|x| x.index() >= self.net.node_count()) {
+            return Err(Error::NoSuchNode(*x));
+        }
+        if let Some(w) = w {
+            if w == 0 || (w as u64) >> self.cache.bits() != 0 {
+                return Err(Error::InvalidEdgeType(w));
+            }
+        }
+        let old = self.net.find_edge(u, v).map(|e| self.net[e]);
+        if old == w {
+            return Ok(old);
+        }
+        let seed = if u == v { vec![u] } else { vec![u, v] };
+        let subgraphs = if seed.len() <= self.cache.k() {
+            connected_supersets(&self.net, seed, self.cache.k())
+        } else {
+            Vec::new()
+        };
+
+        let mut before = Vec::with_capacity(subgraphs.len());
+        for nodes in &subgraphs {
+            if old.is_none() && !is_connected(&self.net, nodes) {
+                continue;
+            }
+            let id = try!(self.cache.canonical_id(&self.net, nodes));
+            if !self.counts.contains_key(&id) {
+                return Err(Error::NoSuchMotif(id));
+            }
+            before.push(id);
+        }
+        for id in before {
+            let count = self.counts.get_mut(&id).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&id);
+            }
+        }
+        match (self.net.find_edge(u, v), w) {
+            (Some(e), Some(w)) => self.net[e] = w,
+            (Some(e), None) => {
+                self.net.remove_edge(e);
+            }
+            (None, Some(w)) => {
+                self.net.add_edge(u, v, w);
+            }
+            (None, None) => {}
+        }
+        for nodes in &subgraphs {
+            if w.is_none() && !is_connected(&self.net, nodes) {
+                continue;
+            }
+            let id = try!(self.cache.canonical_id(&self.net, nodes));
+            *self.counts.entry(id).or_insert(0) += 1;
+        }
+        Ok(old)
+    }
+}
+
+/// Every connected k-node set of `net`'s nodes that holds all of `seed`, itself connected.
+fn connected_supersets<D: Directedness>(net: &Network<D>, seed: Vec<NodeIndex>, k: usize) -> Vec<Vec<NodeIndex>> {
+    let mut frontier = Vec::new();
+    for u in &seed {
+        for v in net.neighbors_undirected(*u) {
+            if !seed.contains(&v) && !frontier.contains(&v) {
+                frontier.push(v);
+            }
+        }
+    }
+    let mut seen = HashSet::from_iter(seed.iter().chain(&frontier).cloned());
+    let mut out = Vec::new();
+    grow(net, k, &mut seed.clone(), frontier, &mut seen, &mut out);
+    out
+}
+
+/// Grows `nodes` to k nodes from `frontier` in every way, each set once: each frontier node is
+/// either taken, or left out of every set after that. `seen` holds the nodes in `nodes` and
+/// `frontier`, and those left out.
+fn grow<D: Directedness>(net: &Network<D>,
+                         k: usize,
+                         nodes: &mut Vec<NodeIndex>,
+                         mut frontier: Vec<NodeIndex>,
+                         seen: &mut HashSet<NodeIndex>,
+                         out: &mut Vec<Vec<NodeIndex>>) {
+    if nodes.len() == k {
+        out.push(nodes.clone());
+        return;
+    }
+    while let Some(w) = frontier.pop() {
+        let mut next = frontier.clone();
+        let mut added = Vec::new();
+        for x in net.neighbors_undirected(w) {
+            if seen.insert(x) {
+                added.push(x);
+                next.push(x);
+            }
+        }
+        nodes.push(w);
+        grow(net, k, nodes, next, seen, out);
+        nodes.pop();
+        for x in added {
+            seen.remove(&x);
+        }
+    }
+}
+
+/// Whether `nodes` induce a connected subgraph of `net`, ignoring edge directions.
+fn is_connected<D: Directedness>(net: &Network<D>, nodes: &[NodeIndex]) -> bool {
+    let mut reached = vec![nodes[0]];
+    let mut i = 0;
+    while i < reached.len() {
+        for v in net.neighbors_undirected(reached[i]) {
+            if nodes.contains(&v) && !reached.contains(&v) {
+                reached.push(v);
+            }
+        }
+        i += 1;
+    }
+    reached.len() == nodes.len()
+}
+
+#[cfg(test)]
+fn random_network<D: Directedness>(rng: &mut ::randomize::Rng, n: usize, m: usize) -> Network<D> {
+    let mut net = Network::with_capacity(n, m);
+    for i in 0..n {
+        net.add_node(i.to_string());
+    }
+    for _ in 0..m {
+        let (u, v) = (NodeIndex::new(rng.below(n)), NodeIndex::new(rng.below(n)));
+        if u != v && net.find_edge(u, v).is_none() {
+            net.add_edge(u, v, 1);
+        }
+    }
+    net
+}
+
+#[cfg(test)]
+fn check_edits<D: Directedness>(k: usize, seed: u64) {
+    let mut rng = ::randomize::Rng::new(seed);
+    let n = 12;
+    let mut counter = IncrementalCounter::new(k, random_network::<D>(&mut rng, n, 20)).unwrap();
+    for _ in 0..60 {
+        let (u, v) = (NodeIndex::new(rng.below(n)), NodeIndex::new(rng.below(n)));
+        if u == v {
+            continue;
+        }
+        let w = 1 + rng.below(3) as EdgeType;
+        let old = counter.network().find_edge(u, v).map(|e| counter.network()[e]);
+        match rng.below(3) {
+            0 => counter.add_edge(u, v, w).unwrap(),
+            1 => assert_eq!(old, counter.remove_edge(u, v).unwrap()),
+            _ => assert_eq!(old, counter.set_edge_type(u, v, w).unwrap()),
+        }
+        assert_eq!(&enumerate_subgraphs(k, counter.network()).unwrap(), counter.counts());
+    }
+}
+
+#[test]
+fn test_incremental_counter() {
+    for k in 2..5 {
+        check_edits::<Directed>(k, k as u64);
+        check_edits::<Undirected>(k, k as u64);
+    }
+}
+
+#[test]
+fn test_incremental_counter_errors() {
+    let mut counter = IncrementalCounter::new(3, network_from_paper()).unwrap();
+    let before = counter.counts().clone();
+    let (a, b) = (NodeIndex::new(0), NodeIndex::new(1));
+    assert!(counter.add_edge(a, NodeIndex::new(16), 1).is_err());
+    assert!(counter.set_edge_type(a, b, 0).is_err());
+    // Two bits per digit, so no room for a third colour.
+    assert!(counter.set_edge_type(a, b, 4).is_err());
+    assert_eq!(&before, counter.counts());
+
+    let mut counter = IncrementalCounter::with_bits(3, network_from_paper(), 4).unwrap();
+    counter.set_edge_type(a, b, 4).unwrap();
+    assert_eq!(&count_with_bits(counter.network(), 4), counter.counts());
+    assert_eq!(Some(4), counter.remove_edge(a, b).unwrap());
+    assert_eq!(None, counter.remove_edge(a, b).unwrap());
+}
+
+#[test]
+fn test_incremental_counter_multigraph() {
+    let mut net = network_from_paper();
+    let (a, b, c) = (NodeIndex::new(0), NodeIndex::new(1), NodeIndex::new(2));
+    net.add_edge(b, a, 2);
+    net.add_edge(b, c, 1);
+    net.add_edge(b, c, 2);
+    let mut counter = IncrementalCounter::new(3, net.clone()).unwrap();
+    assert_eq!(&enumerate_subgraphs(3, &net).unwrap(), counter.counts());
+    assert_eq!(net.edge_count() - 2, counter.network().edge_count());
+
+    assert_eq!(Some(3), counter.remove_edge(b, a).unwrap());
+    assert_eq!(&enumerate_subgraphs(3, counter.network()).unwrap(), counter.counts());
+    assert_eq!(Some(3), counter.set_edge_type(b, c, 1).unwrap());
+    assert_eq!(&enumerate_subgraphs(3, counter.network()).unwrap(), counter.counts());
+    counter.add_edge(c, a, 2).unwrap();
+    assert_eq!(&enumerate_subgraphs(3, counter.network()).unwrap(), counter.counts());
+}
+
+#[cfg(test)]
+fn count_with_bits(net: &Network, bits: usize) -> MotifFreq {
+    enumerate_subgraphs_with(net, &[], &mut CanonCache::new(3, bits).unwrap()).unwrap()
+}
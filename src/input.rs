@@ -0,0 +1,406 @@
+use error::*;
+use network::*;
+use temporal::*;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::path::Path;
+
+/// Parses the interaction column of an edge list. Accepts any positive `EdgeType` code, the `-1`
+/// used for dual edges in adjacency matrices, and the usual names and signs of activation and
+/// repression.
+pub fn parse_edge_type(s: &str) -> Option<EdgeType> {
+    match &*s.to_lowercase() {
+        "+" | "->" | "activation" | "activates" | "activator" | "positive" => Some(1),
+        "-" | "-|" | "repression" | "represses" | "repressor" | "inhibition" | "inhibits" |
+        "negative" => Some(2),
+        "-1" | "+-" | "-+" | "dual" | "both" => Some(3),
+        s => s.parse().ok().filter(|w| *w > 0),
+    }
+}
+
+/// The edge types of an edge list, giving each type that `parse_edge_type` does not know the next
+/// free bit.
+///
+/// Numeric codes are bitmasks, so one with bits above activation and repression would share them
+/// with the names. A list may use either, but not both.
+#[derive(Default)]
+struct EdgeTypeNames {
+    colours: HashMap<String, EdgeType>,
+    /// Whether a numeric code with bits above activation and repression has been seen.
+    numeric: bool,
+}
+
+impl EdgeTypeNames {
+    fn parse(&mut self, lineno: usize, s: &str) -> Result<EdgeType> {
+        match parse_edge_type(s) {
+            Some(w) if w > 3 && !self.colours.is_empty() => {
+                Err(Error::parse_at(lineno, format!("numeric interaction type {:?} in a list with named types", s)))
+            }
+            Some(w) => {
+                self.numeric |= w > 3;
+                Ok(w)
+            }
+            None if self.numeric && s.starts_with(char::is_alphabetic) => {
+                Err(Error::parse_at(lineno,
+                                    format!("named interaction type {:?} in a list with numeric codes above 3", s)))
+            }
+            None if s.starts_with(char::is_alphabetic) => {
+                // Bits 0 and 1 are activation and repression.
+                let bit = self.colours.len() + 2;
+                let name = s.to_lowercase();
+                if !self.colours.contains_key(&name) && bit >= mem::size_of::<EdgeType>() * 8 {
+                    return Err(Error::parse_at(lineno, format!("too many interaction types at {:?}", s)));
+                }
+                Ok(*self.colours.entry(name).or_insert(1 << bit))
+            }
+            None => Err(Error::parse_at(lineno, format!("unknown interaction type {:?}", s))),
+        }
+    }
+}
+
+/// Reads a network from lines of `source target [type]`, separated by tabs or spaces. Anything
+/// after a `#` is a comment. A missing type is an activation.
+///
+/// Types that `parse_edge_type` does not know, such as `phosphorylation`, are further edge
+/// colours, given the bits after repression in order of first appearance. They must start with a
+/// letter, and cannot be mixed with numeric codes above 3, which would take the same bits.
+///
+/// Node names are kept as the node weights, in order of first appearance. Self-loops are dropped,
+/// and repeated edges are merged, so an activation and a repression of the same pair give a dual
+/// edge.
+pub fn parse_edge_list<R: BufRead>(reader: R) -> Result<Network> {
+    let mut net = Network::new();
+    let mut nodes = HashMap::new();
+    let mut types = EdgeTypeNames::default();
+    for (lineno, line) in reader.lines().enumerate() {
+        let line = try!(line);
+        let line = line.split('#').next().unwrap();
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let weight = match fields.len() {
+            0 => continue,
+            2 => 1,
+            3 => try!(types.parse(lineno, fields[2])),
+            _ => return Err(Error::parse_at(lineno, format!("expected `source target [type]`, got {:?}", line))),
+        };
+        let mut node = |name: &str| {
+            *nodes.entry(name.to_string()).or_insert_with(|| net.add_node(name.to_string()))
+        };
+        let (u, v) = (node(fields[0]), node(fields[1]));
+        if u == v {
+            continue;
+        }
+        match net.find_edge(u, v) {
+            Some(e) => net[e] |= weight,
+            None => {
+                net.add_edge(u, v, weight);
+            }
+        }
+    }
+    Ok(net)
+}
+
+pub fn read_edge_list<P: AsRef<Path>>(path: P) -> Result<Network> {
+    let file = try!(File::open(path));
+    parse_edge_list(io::BufReader::new(file))
+}
+
+/// Reads a temporal network from lines of `source target [type] time`, like `parse_edge_list`
+/// with a time column. Every line is an event, so repeated pairs are kept as separate events.
+/// Self-loops are dropped.
+pub fn parse_temporal_edge_list<R: BufRead>(reader: R) -> Result<TemporalNetwork> {
+    let mut net = TemporalNetwork::new();
+    let mut nodes = HashMap::new();
+    let mut types = EdgeTypeNames::default();
+    for (lineno, line) in reader.lines().enumerate() {
+        let line = try!(line);
+        let line = line.split('#').next().unwrap();
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let (weight, time) = match fields.len() {
+            0 => continue,
+            3 => (1, fields[2]),
+            4 => (try!(types.parse(lineno, fields[2])), fields[3]),
+            _ => {
+                return Err(Error::parse_at(lineno, format!("expected `source target [type] time`, got {:?}", line)))
+            }
+        };
+        let time = try!(time.parse().map_err(|_| Error::parse_at(lineno, format!("invalid time {:?}", time))));
+        let mut node = |name: &str| *nodes.entry(name.to_string()).or_insert_with(|| net.add_node(name.to_string()));
+        let (u, v) = (node(fields[0]), node(fields[1]));
+        if u != v {
+            net.add_event(u, v, weight, time);
+        }
+    }
+    net.sort_events();
+    Ok(net)
+}
+
+pub fn read_temporal_edge_list<P: AsRef<Path>>(path: P) -> Result<TemporalNetwork> {
+    let file = try!(File::open(path));
+    parse_temporal_edge_list(io::BufReader::new(file))
+}
+
+/// Reads a network from a square adjacency matrix of whitespace-separated entries, where row `i`
+/// column `j` is the `EdgeType` of `i -> j`: `0` for no edge, or `-1` for a dual edge.
+///
+/// Nodes are named `1` to `n`. The diagonal is ignored.
+pub fn parse_matrix<R: Read>(mut reader: R) -> Result<Network> {
+    let mut s = String::new();
+    try!(reader.read_to_string(&mut s));
+    let mut adj_mat = Vec::new();
+    for (lineno, line) in s.lines().enumerate() {
+        for f in line.split_whitespace() {
+            adj_mat.push(match f {
+                "-1" => 3,
+                _ => try!(f.parse::<EdgeType>()
+                    .map_err(|_| Error::parse_at(lineno, format!("unknown matrix entry {:?}", f)))),
+            });
+        }
+    }
+    let n = (adj_mat.len() as f64).sqrt().round() as usize;
+    if n * n != adj_mat.len() {
+        return Err(Error::Parse {
+            line: None,
+            msg: format!("{} entries do not make a square matrix", adj_mat.len()),
+        });
+    }
+
+    let mut net = Network::with_capacity(n, adj_mat.iter().filter(|v| **v != 0).count());
+    for i in 0..n {
+        net.add_node(format!("{}", i + 1));
+    }
+    for i in (0..n).map(NodeIndex::new) {
+        for j in (0..n).map(NodeIndex::new) {
+            let w = adj_mat[n * i.index() + j.index()];
+            if i != j && w != 0 {
+                net.add_edge(i, j, w);
+            }
+        }
+    }
+    Ok(net)
+}
+
+pub fn read_matrix<P: AsRef<Path>>(path: P) -> Result<Network> {
+    parse_matrix(try!(File::open(path)))
+}
+
+/// Reads the categories of `net`'s nodes from lines of `node category`, separated by tabs or
+/// spaces, with `#` comments as in `parse_edge_list`.
+///
+/// Categories are numbered from 1 in order of first appearance, and their names returned in that
+/// order. Nodes that are not listed get colour 0.
+pub fn parse_node_colours<R: BufRead, D: Directedness>(reader: R, net: &Network<D>)
+                                                      -> Result<(Vec<NodeColour>, Vec<String>)> {
+    let nodes: HashMap<_, _> = net.node_indices().map(|n| (&net[n][..], n)).collect();
+    let mut colours = vec![0; net.node_count()];
+    let mut categories = Vec::new();
+    for (lineno, line) in reader.lines().enumerate() {
+        let line = try!(line);
+        let line = line.split('#').next().unwrap();
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let (node, category) = match fields.len() {
+            0 => continue,
+            2 => (fields[0], fields[1]),
+            _ => return Err(Error::parse_at(lineno, format!("expected `node category`, got {:?}", line))),
+        };
+        let n = match nodes.get(node) {
+            Some(n) => n.index(),
+            None => return Err(Error::parse_at(lineno, format!("no node named {:?} in the network", node))),
+        };
+        let colour = match categories.iter().position(|c| c == category) {
+            Some(i) => i + 1,
+            None => {
+                categories.push(category.to_string());
+                categories.len()
+            }
+        } as NodeColour;
+        if colours[n] != 0 && colours[n] != colour {
+            return Err(Error::parse_at(lineno, format!("node {:?} is already a {}", node,
+                                                       categories[colours[n] as usize - 1])));
+        }
+        colours[n] = colour;
+    }
+    Ok((colours, categories))
+}
+
+pub fn read_node_colours<P: AsRef<Path>, D: Directedness>(path: P, net: &Network<D>)
+                                                         -> Result<(Vec<NodeColour>, Vec<String>)> {
+    let file = try!(File::open(path));
+    parse_node_colours(io::BufReader::new(file), net)
+}
+
+/// Writes `net` as an edge list that `parse_edge_list` reads back. Undirected edges are written
+/// once, either way round.
+pub fn write_edge_list<W: Write, D: Directedness>(mut writer: W, net: &Network<D>) -> io::Result<()> {
+    for e in net.raw_edges() {
+        let kind = match e.weight {
+            1 => "activation".to_string(),
+            2 => "repression".to_string(),
+            3 => "dual".to_string(),
+            w => w.to_string(),
+        };
+        try!(writeln!(writer, "{}\t{}\t{}", net[e.source()], net[e.target()], kind));
+    }
+    Ok(())
+}
+
+/// Writes `net` as an adjacency matrix that `parse_matrix` reads back. Node names are not kept.
+/// The matrix of an undirected network is symmetric.
+pub fn write_matrix<W: Write, D: Directedness>(mut writer: W, net: &Network<D>) -> io::Result<()> {
+    let n = net.node_count();
+    let mut adj_mat = vec![0; n * n];
+    for e in net.raw_edges() {
+        adj_mat[n * e.source().index() + e.target().index()] |= e.weight;
+        if !D::is_directed() {
+            adj_mat[n * e.target().index() + e.source().index()] |= e.weight;
+        }
+    }
+    for row in adj_mat.chunks(n) {
+        let entries: Vec<_> = row.iter().map(|&w| if w == 3 { "-1".to_string() } else { w.to_string() }).collect();
+        try!(writeln!(writer, "{}", entries.join(" ")));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_edge_list() {
+    let text = "# regulator\ttarget\ttype\n\
+                crp\tgalS\tactivation\n\
+                crp galE +  # trailing comment\n\
+                \n\
+                galS galE repression\n\
+                galS galE 1\n\
+                galE galE -\n\
+                fis crp\n";
+    let net = parse_edge_list(text.as_bytes()).unwrap();
+    assert_eq!(4, net.node_count());
+    assert_eq!(4, net.edge_count());
+    let names: Vec<_> = net.node_indices().map(|n| net[n].clone()).collect();
+    assert_eq!(vec!["crp", "galS", "galE", "fis"], names);
+    let node = |name: &str| net.node_indices().find(|n| net[*n] == name).unwrap();
+    let weight = |u, v| net[net.find_edge(node(u), node(v)).unwrap()];
+    assert_eq!(1, weight("crp", "galS"));
+    assert_eq!(1, weight("crp", "galE"));
+    assert_eq!(3, weight("galS", "galE"));
+    assert_eq!(1, weight("fis", "crp"));
+}
+
+#[test]
+fn test_parse_edge_list_errors() {
+    match parse_edge_list("a b\nb c 0\n".as_bytes()) {
+        Err(Error::Parse { line: Some(2), .. }) => {}
+        r => panic!("expected a parse error on line 2, got {:?}", r),
+    }
+    assert!(parse_edge_list("a\n".as_bytes()).is_err());
+    assert!(parse_edge_list("a b 1 2\n".as_bytes()).is_err());
+    assert!(parse_edge_list("a b -2\n".as_bytes()).is_err());
+}
+
+#[test]
+fn test_parse_temporal_edge_list() {
+    let text = "a b 10\n\
+                b c repression 5  # out of order\n\
+                a b ppi 10\n\
+                c c 7\n\
+                a b -3\n";
+    let net = parse_temporal_edge_list(text.as_bytes()).unwrap();
+    assert_eq!(3, net.net.node_count());
+    assert_eq!(2, net.net.edge_count());
+    let events: Vec<_> = net.events.iter().map(|e| (e.source.index(), e.target.index(), e.edge_type, e.time)).collect();
+    assert_eq!(vec![(0, 1, 1, -3), (1, 2, 2, 5), (0, 1, 1, 10), (0, 1, 4, 10)], events);
+
+    match parse_temporal_edge_list("a b 1\na b soon\n".as_bytes()) {
+        Err(Error::Parse { line: Some(2), .. }) => {}
+        r => panic!("expected a parse error on line 2, got {:?}", r.map(|net| net.events)),
+    }
+    assert!(parse_temporal_edge_list("a b\n".as_bytes()).is_err());
+    assert!(parse_temporal_edge_list("a b 0 1\n".as_bytes()).is_err());
+}
+
+#[test]
+fn test_parse_edge_list_colours() {
+    let text = "a b ppi\n\
+                b c Phosphorylation\n\
+                a b activation\n\
+                c a ppi\n\
+                c d 3\n";
+    let net = parse_edge_list(text.as_bytes()).unwrap();
+    let weight = |u: usize, v: usize| net[net.find_edge(NodeIndex::new(u), NodeIndex::new(v)).unwrap()];
+    assert_eq!(4 | 1, weight(0, 1));
+    assert_eq!(8, weight(1, 2));
+    assert_eq!(4, weight(2, 0));
+    assert_eq!(3, weight(2, 3));
+    assert_eq!(4, colour_count(&net));
+
+    let net = parse_edge_list("a b 16
+b c 2
+".as_bytes()).unwrap();
+    assert_eq!(5, colour_count(&net));
+
+    // Names take the bits from 4 up, so they cannot share a list with codes that use them.
+    for &(text, line) in &[("a b 4
+c d ppi
+", 2), ("a b phosphorylation
+c d +
+c d 8
+", 3)] {
+        match parse_edge_list(text.as_bytes()) {
+            Err(Error::Parse { line: Some(l), .. }) if l == line => {}
+            r => panic!("expected a parse error on line {} of {:?}, got {:?}", line, text, r),
+        }
+    }
+
+    let many: String = (0..31).map(|i| format!("a b type{}\n", i)).collect();
+    match parse_edge_list(many.as_bytes()) {
+        Err(Error::Parse { line: Some(31), .. }) => {}
+        r => panic!("expected a parse error on line 31, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_parse_node_colours() {
+    let net = parse_edge_list("crp galS\ncrp galE\nfis crp\nmir1 galE -\n".as_bytes()).unwrap();
+    let text = "# node\tcategory\n\
+                crp tf\n\
+                galS gene\n\
+                fis tf  # also regulates itself\n\
+                galE gene\n\
+                galE gene\n";
+    let (colours, categories) = parse_node_colours(text.as_bytes(), &net).unwrap();
+    assert_eq!(vec![1, 2, 2, 1, 0], colours);
+    assert_eq!(vec!["tf", "gene"], categories);
+
+    match parse_node_colours("crp tf\ncrp gene\n".as_bytes(), &net) {
+        Err(Error::Parse { line: Some(2), .. }) => {}
+        r => panic!("expected a parse error on line 2, got {:?}", r),
+    }
+    assert!(parse_node_colours("lacZ gene\n".as_bytes(), &net).is_err());
+    assert!(parse_node_colours("crp\n".as_bytes(), &net).is_err());
+}
+
+#[test]
+fn test_matrix_roundtrip() {
+    let net = parse_matrix("0 1 0\n-1 0 2\n0 0 1\n".as_bytes()).unwrap();
+    assert_eq!(3, net.node_count());
+    assert_eq!(3, net.edge_count());
+    assert_eq!(3, net[net.find_edge(NodeIndex::new(1), NodeIndex::new(0)).unwrap()]);
+    let mut text = Vec::new();
+    write_matrix(&mut text, &net).unwrap();
+    assert_eq!("0 1 0\n-1 0 2\n0 0 0\n", String::from_utf8(text).unwrap());
+
+    let mut text = Vec::new();
+    write_edge_list(&mut text, &net).unwrap();
+    let again = parse_edge_list(&text[..]).unwrap();
+    assert_eq!(::motifs::motif_id(&net, 2).unwrap(), ::motifs::motif_id(&again, 2).unwrap());
+
+    let mut text = Vec::new();
+    write_matrix(&mut text, &with_directedness::<Undirected>(&net)).unwrap();
+    assert_eq!("0 -1 0\n-1 0 2\n0 2 0\n", String::from_utf8(text).unwrap());
+
+    assert!(parse_matrix("0 1\n1\n".as_bytes()).is_err());
+    let err = parse_matrix("0 1\n1 x\n".as_bytes()).unwrap_err();
+    assert!(err.to_string().starts_with("line 2:"));
+}
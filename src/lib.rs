@@ -1,12 +1,24 @@
-#![feature(static_mutex)]
-
 extern crate arrayvec;
 extern crate fixedbitset;
 extern crate libc;
 extern crate petgraph;
 
+pub mod canon;
+pub mod csr;
+pub mod error;
+pub mod incremental;
+pub mod inclusion;
+pub mod input;
 pub mod motifs;
 pub mod nauty;
 #[allow(non_camel_case_types)]
 pub mod nauty_bindings;
 pub mod network;
+pub mod randomize;
+pub mod render;
+pub mod report;
+pub mod results;
+pub mod stats;
+pub mod temporal;
+
+pub use error::{Error, Result};
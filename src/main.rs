@@ -1,46 +1,94 @@
-#![feature(alloc_system)]
-
-extern crate alloc_system;
 extern crate rustmotifs;
 
+use rustmotifs::canon::*;
+use rustmotifs::inclusion::*;
+use rustmotifs::input::*;
 use rustmotifs::network::*;
 use rustmotifs::motifs::*;
+use rustmotifs::randomize::*;
+use rustmotifs::render::{arrowhead, circular_layout, write_svg, write_svg_body, write_svg_markers};
+use rustmotifs::report::*;
+use rustmotifs::results::*;
+use rustmotifs::stats::*;
+use rustmotifs::temporal::*;
 
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
 use std::io::prelude::*;
 use std::iter::FromIterator;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::str::FromStr;
+use std::time::Instant;
 
+const USAGE: &'static str = "\
+Usage: rustmotifs <command> [options] <network>
+
+Commands:
+  count          count the k-node motifs of a network
+  significance   compare the counts with those of randomized networks
+  randomize      write randomized copies of a network
+  render         draw each motif as SVG and dot files, with an HTML index
+  instances      list the nodes of each occurrence of a motif
+  temporal       count the delta-temporal motifs of a network of timestamped events
+
+Options:
+  -k, --size K          motif size (default 3)
+  -o, --output DIR      write results under DIR/<network>/k<K>/ (default .)
+      --force           overwrite the results of an earlier run
+  -f, --format FORMAT   network file format, matrix or edges (default matrix)
+  -u, --undirected      ignore edge directions and count undirected motifs
+  -n, --ensemble N      number of randomized networks (default 100)
+  -s, --seed S          seed for randomizing and sampling (default 0)
+      --swaps X         edge switches per edge when randomizing (default 100)
+  -t, --threads T       worker threads (default one per CPU)
+      --sample P,..     count: estimate by RAND-ESU, visiting depth d with probability P_d
+      --orbits          count: also write per-node orbit counts to orbits.tsv
+      --non-induced     count: also count each motif's copies that have extra edges between
+                        their nodes
+      --motifs ID,..    instances: only list these motifs (default all)
+      --json            count, significance: also write the results to results.json
+      --node-colours F  count, significance: tell motifs apart by node category, read from
+                        lines of `node category` in F
+      --events L        temporal: events per motif (default 3)
+      --delta D         temporal: time window each motif's events must fit in
+  -h, --help            show this message
+
+significance writes stats.csv and a standalone report.html, and compares against the
+networks given after <network>, if any, instead of randomizing it. randomize writes its
+networks to DIR/<network>/random/.
+
+With --node-colours, categories are numbered from 1 in order of first appearance in F, and
+nodes not in F are 0.
+
+temporal reads an edge list with a time column, `source target [type] time`, and counts
+sequences of L events on k connected nodes, all within D of the first.
+
+Exit status is 0 on success, 1 if the analysis fails and 2 for usage errors.
+";
 
-fn arrowhead(w: EdgeType) -> &'static str {
-    match w {
-        1 => "onormal",
-        3 => "diamond",
-        _ => "circle",
-    }
-}
 
 #[allow(unused_must_use)]
-fn gen_dot(net: &Network) -> Vec<u8> {
+fn gen_dot<D: Directedness>(net: &Network<D>) -> Vec<u8> {
     let mut graph = Vec::new();
     let n = net.node_count();
+    let (kind, arrow) = if D::is_directed() { ("digraph", "->") } else { ("graph", "--") };
     {
         let g = &mut graph;
-        writeln!(g, "digraph {{");
-        for i in 0..n {
-            let pi = std::f64::consts::PI;
-            let t = 2. * pi * i as f64 / n as f64;
-            writeln!(g, "  {} [pin=true,pos=\"{:.3},{:.3}\",shape=point]", i, t.sin(), t.cos());
+        writeln!(g, "{} {{", kind);
+        for (i, (x, y)) in circular_layout(n).into_iter().enumerate() {
+            writeln!(g, "  {} [pin=true,pos=\"{:.3},{:.3}\",shape=point]", i, x, y);
         }
         for e in net.raw_edges() {
-            writeln!(g, "  {} -> {} [arrowhead={}]", e.source().index(), e.target().index(), arrowhead(e.weight));
+            writeln!(g, "  {} {} {} [arrowhead={}]", e.source().index(), arrow, e.target().index(), arrowhead(e.weight));
         }
         write!(g, "}}");
     }
     graph
 }
 
-fn is_interesting(motif: &Network) -> bool {
+fn is_interesting<D: Directedness>(motif: &Network<D>) -> bool {
     let mut total_degree = 0;
     for n in 0..motif.node_count() {
         total_degree += std::collections::HashSet::<NodeIndex>::from_iter(motif.neighbors_undirected(NodeIndex::new(n))).len();
@@ -48,94 +96,630 @@ fn is_interesting(motif: &Network) -> bool {
     total_degree > 2 * (motif.node_count() - 1)
 }
 
-fn read_net<P: AsRef<Path>>(path: P) -> (Network, usize) {
-    let mut file = std::fs::File::open(path).unwrap();
-    let mut s = String::new();
-    file.read_to_string(&mut s).unwrap();
-    let adj_mat = Vec::from_iter(s.split_whitespace().map(|f| match f {
-        "1" => 1,
-        "2" => 2,
-        "-1" => 3,
-        "0" => 0,
-        s => panic!("{:?}", s),
-    }));
-    let n = (adj_mat.len() as f64 + 1.0).sqrt() as usize;
-    let mut net = Network::with_capacity(n, adj_mat.iter().filter(|v| **v != 0).count());
-    for i in 0..n {
-        net.add_node(format!("{}", i + 1));
-    }
-    for i in (0..n).map(NodeIndex::new) {
-        for j in (0..n).map(NodeIndex::new) {
-            let w = adj_mat[n * i.index() + j.index()];
-            if i != j && w != 0 {
-                net.add_edge(i, j, w);
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Command {
+    Count,
+    Significance,
+    Randomize,
+    Render,
+    Instances,
+    Temporal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Matrix,
+    EdgeList,
+}
+
+impl Format {
+    fn read<P: AsRef<Path>>(self, path: P) -> rustmotifs::Result<Network> {
+        match self {
+            Format::Matrix => read_matrix(path),
+            Format::EdgeList => read_edge_list(path),
+        }
+    }
+
+    fn write<W: Write, D: Directedness>(self, writer: W, net: &Network<D>) -> io::Result<()> {
+        match self {
+            Format::Matrix => write_matrix(writer, net),
+            Format::EdgeList => write_edge_list(writer, net),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Config {
+    command: Command,
+    networks: Vec<PathBuf>,
+    k: usize,
+    output: PathBuf,
+    force: bool,
+    format: Format,
+    undirected: bool,
+    ensemble: usize,
+    seed: u64,
+    swaps: usize,
+    threads: usize,
+    sample: Option<Vec<f64>>,
+    orbits: bool,
+    non_induced: bool,
+    json: bool,
+    motifs: Option<BTreeSet<MotifId>>,
+    node_colours: Option<PathBuf>,
+    events: Option<usize>,
+    delta: Option<Timestamp>,
+}
+
+enum CliError {
+    /// Bad arguments: exit status 2.
+    Usage(String),
+    /// The analysis itself failed: exit status 1.
+    Failed(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CliError::Usage(ref msg) | CliError::Failed(ref msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl From<rustmotifs::Error> for CliError {
+    fn from(e: rustmotifs::Error) -> CliError {
+        CliError::Failed(e.to_string())
+    }
+}
+
+fn usage<T>(msg: String) -> Result<T, CliError> {
+    Err(CliError::Usage(msg))
+}
+
+/// Adds the file an error happened on to its message.
+fn failed<P: AsRef<Path>, E: fmt::Display>(path: P) -> impl FnOnce(E) -> CliError {
+    move |e| CliError::Failed(format!("{}: {}", path.as_ref().display(), e))
+}
+
+/// The directory under `--output` for results about the first network, in a subdirectory
+/// `sub`, created if needed.
+fn output_dir(config: &Config, sub: &str) -> Result<PathBuf, CliError> {
+    let stem = config.networks[0].file_stem().unwrap_or("network".as_ref());
+    let dir = config.output.join(stem).join(sub);
+    try!(std::fs::create_dir_all(&dir).map_err(failed(&dir)));
+    Ok(dir)
+}
+
+/// Fails if `path` exists and `--force` was not given. Outputs are checked before the work that
+/// fills them, so a run that could not write its results stops straight away.
+fn check_new(config: &Config, path: &Path) -> Result<(), CliError> {
+    if !config.force && path.exists() {
+        return Err(CliError::Failed(format!("{} already exists; use --force to overwrite it", path.display())));
+    }
+    Ok(())
+}
+
+/// Writes `path` with `write`, unless it exists and `--force` was not given. The output goes to a
+/// temporary file in the same directory that is renamed into place when complete, so a failed
+/// write leaves nothing behind.
+fn write_file<F>(config: &Config, path: &Path, write: F) -> Result<(), CliError>
+    where F: FnOnce(&mut io::BufWriter<std::fs::File>) -> io::Result<()>
+{
+    try!(check_new(config, path));
+    let name = path.file_name().map_or("output".into(), |n| n.to_string_lossy());
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name, process::id()));
+    let written = std::fs::File::create(&tmp).and_then(|file| {
+        let mut writer = io::BufWriter::new(file);
+        try!(write(&mut writer));
+        try!(writer.flush());
+        // Another run may have written it in the meantime.
+        if !config.force && path.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "already exists; use --force to overwrite it"));
+        }
+        std::fs::rename(&tmp, path)
+    });
+    written.map_err(|e| {
+        let _ = std::fs::remove_file(&tmp);
+        failed(path)(e)
+    })
+}
+
+fn k_dir(config: &Config) -> Result<PathBuf, CliError> {
+    output_dir(config, &format!("k{}", config.k))
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, CliError> {
+    T::from_str(value).or_else(|_| usage(format!("invalid value for {}: {:?}", name, value)))
+}
+
+fn parse_list<T: FromStr>(name: &str, value: &str) -> Result<Vec<T>, CliError> {
+    value.split(',').map(|v| parse_value(name, v.trim())).collect()
+}
+
+/// Parses the arguments after the program name. `Ok(None)` means help was asked for.
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Config>, CliError> {
+    let command = match args.next().as_ref().map(|c| &c[..]) {
+        Some("count") => Command::Count,
+        Some("significance") => Command::Significance,
+        Some("randomize") => Command::Randomize,
+        Some("render") => Command::Render,
+        Some("instances") => Command::Instances,
+        Some("temporal") => Command::Temporal,
+        Some("-h") | Some("--help") | Some("help") => return Ok(None),
+        Some(c) => return usage(format!("unknown command {:?}", c)),
+        None => return usage("no command given".to_string()),
+    };
+    let mut config = Config {
+        command: command,
+        networks: Vec::new(),
+        k: 3,
+        output: PathBuf::from("."),
+        force: false,
+        format: Format::Matrix,
+        undirected: false,
+        ensemble: 100,
+        seed: 0,
+        swaps: DEFAULT_SWAPS_PER_EDGE,
+        threads: default_threads(),
+        sample: None,
+        orbits: false,
+        non_induced: false,
+        json: false,
+        motifs: None,
+        node_colours: None,
+        events: None,
+        delta: None,
+    };
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            config.networks.push(PathBuf::from(arg));
+            continue;
+        }
+        // Both `--name value` and `--name=value` work.
+        let (name, mut inline) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || match inline.take().or_else(|| args.next()) {
+            Some(v) => Ok(v),
+            None => usage(format!("{} needs a value", name)),
+        };
+        match &name[..] {
+            "-h" | "--help" => return Ok(None),
+            "-k" | "--size" => config.k = try!(parse_value(&name, &try!(value()))),
+            "-o" | "--output" => config.output = PathBuf::from(try!(value())),
+            "--force" => config.force = true,
+            "-f" | "--format" => {
+                config.format = match &try!(value())[..] {
+                    "matrix" => Format::Matrix,
+                    "edges" | "edgelist" => Format::EdgeList,
+                    f => return usage(format!("unknown format {:?}, expected matrix or edges", f)),
+                }
             }
+            "-u" | "--undirected" => config.undirected = true,
+            "-n" | "--ensemble" => config.ensemble = try!(parse_value(&name, &try!(value()))),
+            "-s" | "--seed" => config.seed = try!(parse_value(&name, &try!(value()))),
+            "--swaps" => config.swaps = try!(parse_value(&name, &try!(value()))),
+            "-t" | "--threads" => config.threads = try!(parse_value(&name, &try!(value()))),
+            "--sample" => config.sample = Some(try!(parse_list(&name, &try!(value())))),
+            "--orbits" => config.orbits = true,
+            "--non-induced" => config.non_induced = true,
+            "--json" => config.json = true,
+            "--motifs" => config.motifs = Some(BTreeSet::from_iter(try!(parse_list(&name, &try!(value()))))),
+            "--node-colours" | "--node-colors" => config.node_colours = Some(PathBuf::from(try!(value()))),
+            "--events" => config.events = Some(try!(parse_value(&name, &try!(value())))),
+            "--delta" => config.delta = Some(try!(parse_value(&name, &try!(value())))),
+            _ => return usage(format!("unknown option {}", name)),
+        }
+        if inline.is_some() {
+            return usage(format!("{} does not take a value", name));
         }
     }
-    (net, n)
+
+    if config.k < 1 || config.k > MAX_K {
+        return usage(format!("k must be between 1 and {}, not {}", MAX_K, config.k));
+    }
+    if config.threads < 1 {
+        return usage("need at least one thread".to_string());
+    }
+    match config.networks.len() {
+        0 => return usage("no network given".to_string()),
+        1 => {}
+        _ if command == Command::Significance => {}
+        _ => return usage("only significance takes more than one network".to_string()),
+    }
+    if let Some(ref p) = config.sample {
+        if command != Command::Count {
+            return usage("--sample only applies to count".to_string());
+        }
+        if p.len() != config.k {
+            return usage(format!("--sample needs {} probabilities, one per depth, not {}", config.k, p.len()));
+        }
+        if !p.iter().all(|p| *p > 0.0 && *p <= 1.0) {
+            return usage("--sample probabilities must be in (0, 1]".to_string());
+        }
+    }
+    if config.orbits && command != Command::Count {
+        return usage("--orbits only applies to count".to_string());
+    }
+    if config.non_induced {
+        if command != Command::Count {
+            return usage("--non-induced only applies to count".to_string());
+        }
+        if config.sample.is_some() {
+            return usage("--non-induced cannot be used with --sample".to_string());
+        }
+    }
+    if config.motifs.is_some() && command != Command::Instances {
+        return usage("--motifs only applies to instances".to_string());
+    }
+    if config.json {
+        if command != Command::Count && command != Command::Significance {
+            return usage("--json only applies to count and significance".to_string());
+        }
+        if config.sample.is_some() {
+            return usage("--json cannot be used with --sample".to_string());
+        }
+    }
+    if config.node_colours.is_some() {
+        if command != Command::Count && command != Command::Significance {
+            return usage("--node-colours only applies to count and significance".to_string());
+        }
+        if config.sample.is_some() || config.orbits {
+            return usage("--node-colours cannot be used with --sample or --orbits".to_string());
+        }
+    }
+    if command == Command::Temporal {
+        if config.delta.is_none() {
+            return usage("temporal needs --delta".to_string());
+        }
+        if config.events == Some(0) {
+            return usage("need at least one event".to_string());
+        }
+        if config.undirected {
+            return usage("--undirected does not apply to temporal".to_string());
+        }
+    } else if config.delta.is_some() || config.events.is_some() {
+        return usage("--delta and --events only apply to temporal".to_string());
+    }
+    Ok(Some(config))
 }
 
 fn main() {
-    let args = Vec::from_iter(std::env::args());
-    let (net, n) = read_net(&args[1]);
-    println!("{}", n);
-    let k = args.get(2).and_then(|k| usize::from_str(k).ok()).unwrap_or(3);
-    let original_motifs = enumerate_subgraphs(k, &net);
-    println!("{:?}", original_motifs);
-    if args.len() <= 3 {
-        for id in original_motifs.keys() {
-            let dot = gen_dot(&id_to_network(k, *id));
-            let mut file = std::fs::File::create(format!("graphs/{}.dot", id)).unwrap();
-            file.write_all(dot.as_slice()).is_ok();
-        }
-        let mut motifs = Vec::from_iter(original_motifs.iter().map(|(id, count)| (*count, *id)));
-        motifs.sort();
-        motifs.reverse();
-        {
-            let mut path = Path::new("graphs").join(Path::new(&args[1]).file_stem().unwrap());
-            path.set_extension("html");
-            let mut html = std::fs::File::create(path).unwrap();
-            write!(&mut html, "<html><body><table>").unwrap();
-            for (count, id) in motifs {
-                if is_interesting(&id_to_network(k, id)) {
-                    write!(&mut html, r#"<tr><td><img src="{}.dot.png"></td><td>{}</td>"#, id, count).unwrap();
+    let config = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("rustmotifs: {}", e);
+            eprintln!("Try 'rustmotifs --help' for more information.");
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(&config) {
+        eprintln!("rustmotifs: {}", e);
+        process::exit(match e {
+            CliError::Usage(_) => 2,
+            CliError::Failed(_) => 1,
+        });
+    }
+}
+
+fn run(config: &Config) -> Result<(), CliError> {
+    if config.command == Command::Temporal {
+        temporal(config)
+    } else if config.undirected {
+        run_with::<Undirected>(config)
+    } else {
+        run_with::<Directed>(config)
+    }
+}
+
+fn run_with<D: Directedness + Sync>(config: &Config) -> Result<(), CliError> {
+    let path = &config.networks[0];
+    let net = try!(read::<D>(config, path));
+    match config.command {
+        Command::Count => count(config, &net),
+        Command::Significance => significance(config, &net),
+        Command::Randomize => randomize_network(config, path, &net),
+        Command::Render => render(config, &net),
+        Command::Instances => instances(config, &net),
+        Command::Temporal => unreachable!("temporal networks are read by temporal"),
+    }
+}
+
+/// Reads the network at `path`, without edge directions if `D` is `Undirected`.
+fn read<D: Directedness>(config: &Config, path: &Path) -> Result<Network<D>, CliError> {
+    let net = try!(config.format.read(path).map_err(failed(path)));
+    Ok(with_directedness(&net))
+}
+
+/// The node colours of `net` from `--node-colours` and the names of the categories, or none.
+fn node_colours<D: Directedness>(config: &Config, net: &Network<D>)
+                                 -> Result<(Vec<NodeColour>, Vec<String>), CliError> {
+    match config.node_colours {
+        Some(ref path) => Ok(try!(read_node_colours(path, net).map_err(failed(path)))),
+        None => Ok((Vec::new(), Vec::new())),
+    }
+}
+
+/// The path of results.json for `--json`, if it was given, checked with `check_new`.
+fn json_output(config: &Config) -> Result<Option<PathBuf>, CliError> {
+    if !config.json {
+        return Ok(None);
+    }
+    let path = try!(k_dir(config)).join("results.json");
+    try!(check_new(config, &path));
+    Ok(Some(path))
+}
+
+/// Writes `analysis` to the `--json` output, if any, timed from `start`.
+fn write_analysis(config: &Config, json: Option<PathBuf>, mut analysis: Analysis, start: Instant)
+                  -> Result<(), CliError> {
+    if let Some(path) = json {
+        let elapsed = start.elapsed();
+        analysis.runtime = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+        try!(write_file(config, &path, |w| write_json(w, &analysis)));
+    }
+    Ok(())
+}
+
+fn analysis_input<D: Directedness>(config: &Config,
+                                   net: &Network<D>,
+                                   ensemble: usize,
+                                   categories: Vec<String>)
+                                   -> Input {
+    Input {
+        path: config.networks[0].display().to_string(),
+        nodes: net.node_count(),
+        edges: net.edge_count(),
+        ensemble: ensemble,
+        node_categories: categories,
+    }
+}
+
+fn count<D: Directedness>(config: &Config, net: &Network<D>) -> Result<(), CliError> {
+    let start = Instant::now();
+    let json = try!(json_output(config));
+    let orbits_path = if config.orbits {
+        let path = try!(k_dir(config)).join("orbits.tsv");
+        try!(check_new(config, &path));
+        Some(path)
+    } else {
+        None
+    };
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let written = match config.sample {
+        Some(ref p) => {
+            let sample = try!(sample_subgraphs(config.k, net, p, config.seed));
+            let (estimates, variances, concentrations) =
+                (sample.estimated_counts(), sample.variances(), sample.concentrations());
+            writeln!(out, "MotifId\tSampled\tEstimate\tVariance\tConcentration").and_then(|_| {
+                for (id, sampled) in &sample.sampled {
+                    try!(writeln!(out, "{}\t{}\t{}\t{}\t{}", id, sampled, estimates[id], variances[id],
+                                  concentrations[id]));
                 }
+                Ok(())
+            })
+        }
+        None => {
+            let (colours, categories) = try!(node_colours(config, net));
+            let counts = try!(enumerate_subgraphs_parallel(config.k, net, &colours, config.threads));
+            let bits = coloured_digit_bits(net, &colours);
+            let input = analysis_input(config, net, 0, categories);
+            if config.non_induced {
+                let mut cache = try!(CanonCache::<D>::new(config.k, bits));
+                let non_induced = non_induced_counts(&counts, &mut cache);
+                let analysis = Analysis::from_non_induced_counts::<D>(input, config.k, bits, !colours.is_empty(),
+                                                                      &counts, &non_induced);
+                try!(write_analysis(config, json, analysis, start));
+                writeln!(out, "MotifId\tCount\tNonInduced").and_then(|_| {
+                    // Every motif is in the non-induced counts, some only as part of bigger ones.
+                    for (id, count) in &non_induced {
+                        try!(writeln!(out, "{}\t{}\t{}", id, counts.get(id).cloned().unwrap_or(0), count));
+                    }
+                    Ok(())
+                })
+            } else {
+                let analysis = Analysis::from_counts::<D>(input, config.k, bits, !colours.is_empty(), &counts);
+                try!(write_analysis(config, json, analysis, start));
+                writeln!(out, "MotifId\tCount").and_then(|_| {
+                    for (id, count) in &counts {
+                        try!(writeln!(out, "{}\t{}", id, count));
+                    }
+                    Ok(())
+                })
             }
-            write!(&mut html, "</table></body></html>").unwrap();
         }
+    };
+    try!(written.map_err(failed("<stdout>")));
+    if let Some(path) = orbits_path {
+        let orbits = try!(orbit_counts(config.k, net));
+        try!(write_file(config, &path, |tsv| write_orbits(tsv, net, &orbits)));
     }
+    Ok(())
+}
 
-    print!("calculating ensemble motifs...");
-    std::io::stdout().flush().unwrap();
-    let mut ensemble_motifs = Vec::new();
-    for (i, file) in args[3..].iter().enumerate() {
-        if i % 10 == 0 {
-            print!(" {}", i);
-            std::io::stdout().flush().unwrap();
+fn temporal(config: &Config) -> Result<(), CliError> {
+    let path = &config.networks[0];
+    let net = try!(read_temporal_edge_list(path).map_err(failed(path)));
+    let counts = try!(temporal_motifs(&net, config.k, config.events.unwrap_or(3), config.delta.unwrap()));
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    writeln!(out, "Motif\tCount").and_then(|_| {
+        for (motif, count) in &counts {
+            try!(writeln!(out, "{}\t{}", motif, count));
+        }
+        Ok(())
+    }).map_err(failed("<stdout>"))
+}
+
+fn significance<D: Directedness + Sync>(config: &Config, net: &Network<D>) -> Result<(), CliError> {
+    // Fail before the expensive part if there are results already.
+    let path = try!(k_dir(config)).join("stats.csv");
+    try!(check_new(config, &path));
+    let report_path = try!(k_dir(config)).join("report.html");
+    try!(check_new(config, &report_path));
+    let start = Instant::now();
+    let json = try!(json_output(config));
+    let (colours, categories) = try!(node_colours(config, net));
+    // One digit width for the original and the ensemble, so their motif ids compare.
+    let bits = coloured_digit_bits(net, &colours);
+    let cache = try!(CanonCache::<D>::new(config.k, bits));
+    let original = try!(enumerate_subgraphs_parallel_with(net, &colours, &cache, config.threads));
+    let mut ensemble_colours = Vec::new();
+    let ensemble = if config.networks.len() > 1 {
+        let mut ensemble = Vec::new();
+        let edge_bits = digit_bits(colour_count(net));
+        for path in &config.networks[1..] {
+            let random = try!(read::<D>(config, path));
+            // Motif ids are in a base that follows the edge colours.
+            if digit_bits(colour_count(&random)) != edge_bits {
+                return Err(CliError::Failed(format!("{}: uses different edge colours from {}",
+                                                    path.display(),
+                                                    config.networks[0].display())));
+            }
+            // Their nodes may be in another order, so match colours up by name.
+            if config.node_colours.is_some() {
+                ensemble_colours.push(try!(node_colours(config, &random)).0);
+            }
+            ensemble.push(random);
+        }
+        ensemble
+    } else {
+        let ensemble = ensemble(net, config.ensemble, config.swaps, config.seed);
+        if !colours.is_empty() {
+            ensemble_colours = vec![colours.clone(); ensemble.len()];
         }
-        let (net, _) = read_net(file);
-        ensemble_motifs.push(enumerate_subgraphs(k, &net));
+        ensemble
+    };
+    eprint!("counting motifs in {} networks...", ensemble.len());
+    let ensemble_motifs = try!(enumerate_ensemble_with(&ensemble, &ensemble_colours, &cache, config.threads));
+    eprintln!(" done");
+    try!(write_file(config, &path, |csv| write_stats(csv, &original, &ensemble_motifs)));
+    let title = config.networks[0].file_stem().unwrap_or("network".as_ref()).to_string_lossy();
+    let stats = motif_stats(&original, &ensemble_motifs);
+    try!(write_file(config, &report_path, |html| write_report::<_, D>(html, &title, config.k, bits, &stats)));
+    let input = analysis_input(config, net, ensemble_motifs.len(), categories);
+    write_analysis(config, json, Analysis::from_stats::<D>(input, config.k, bits, !colours.is_empty(), &stats), start)
+}
+
+fn randomize_network<D: Directedness>(config: &Config, path: &Path, net: &Network<D>) -> Result<(), CliError> {
+    let stem = path.file_stem().unwrap_or("network".as_ref()).to_string_lossy();
+    let dir = try!(output_dir(config, "random"));
+    let mut outs = Vec::new();
+    for i in 0..config.ensemble {
+        let mut name = format!("{}_r{}", stem, i + 1);
+        if let Some(ext) = path.extension() {
+            name = format!("{}.{}", name, ext.to_string_lossy());
+        }
+        let out = dir.join(name);
+        try!(check_new(config, &out));
+        outs.push(out);
+    }
+    let mut rng = Rng::new(config.seed);
+    for out in &outs {
+        let random = randomize(net, config.swaps, &mut rng);
+        try!(write_file(config, out, |file| config.format.write(file, &random)));
+    }
+    Ok(())
+}
+
+fn render<D: Directedness>(config: &Config, net: &Network<D>) -> Result<(), CliError> {
+    let (k, bits) = (config.k, digit_bits(colour_count(net)));
+    let dir = try!(k_dir(config));
+    let index = dir.join("index.html");
+    try!(check_new(config, &index));
+    let original_motifs = try!(enumerate_subgraphs_parallel(k, net, &[], config.threads));
+    for id in original_motifs.keys() {
+        try!(check_new(config, &dir.join(format!("{}.dot", id))));
+        try!(check_new(config, &dir.join(format!("{}.svg", id))));
     }
-    println!(" done");
-    print!("writing stats...");
-    std::io::stdout().flush().unwrap();
-    print_stats(&original_motifs, &ensemble_motifs).unwrap();
-    println!("done");
+    for id in original_motifs.keys() {
+        let motif = id_to_network::<D>(k, bits, id);
+        try!(write_file(config, &dir.join(format!("{}.dot", id)), |file| file.write_all(&gen_dot(&motif))));
+        try!(write_file(config, &dir.join(format!("{}.svg", id)), |file| write_svg(file, &motif)));
+    }
+    let mut motifs = Vec::from_iter(original_motifs.iter().map(|(id, count)| (*count, id.clone())));
+    motifs.sort();
+    motifs.reverse();
+
+    write_file(config, &index, |html| {
+        try!(write!(html, "<html><body>"));
+        try!(write_svg_markers(&mut *html));
+        try!(write!(html, "<table>"));
+        for (count, id) in motifs {
+            let motif = id_to_network::<D>(k, bits, &id);
+            if is_interesting(&motif) {
+                try!(write!(html, "<tr><td>"));
+                try!(write_svg_body(&mut *html, &motif, &[]));
+                try!(write!(html, "</td><td>{}</td>", count));
+            }
+        }
+        write!(html, "</table></body></html>")
+    })
 }
 
-fn print_stats(motifs: &MotifFreq, ensemble_motifs: &Vec<MotifFreq>) -> std::io::Result<()> {
-    let mut stats = try!(std::fs::File::create("stats.csv"));
+fn instances<D: Directedness>(config: &Config, net: &Network<D>) -> Result<(), CliError> {
+    let path = try!(k_dir(config)).join("instances.tsv");
+    try!(check_new(config, &path));
+    let ids = match config.motifs {
+        Some(ref ids) => ids.clone(),
+        None => try!(enumerate_subgraphs_parallel(config.k, net, &[], config.threads)).keys().cloned().collect(),
+    };
+    let instances = try!(motif_instances(config.k, net, &ids));
+    write_file(config, &path, |tsv| write_instances(tsv, net, &instances))
+}
+
+fn write_stats<W: Write>(mut stats: W, motifs: &MotifFreq, ensemble_motifs: &[MotifFreq]) -> io::Result<()> {
     try!(write!(&mut stats, "MotifId,Original"));
     for i in 0..ensemble_motifs.len() {
         try!(write!(&mut stats, ",R{}", i + 1));
     }
-    try!(writeln!(&mut stats, ""));
-    for (motif_id, freq) in motifs {
-        try!(write!(&mut stats, "{},{}", *motif_id, *freq));
+    try!(writeln!(&mut stats, ",Mean,SD,Z,P,SP"));
+    for (motif_id, s) in motif_stats(motifs, ensemble_motifs) {
+        try!(write!(&mut stats, "{},{}", motif_id, s.original));
         for en in ensemble_motifs {
-            try!(write!(&mut stats, ",{}", *en.get(motif_id).unwrap_or(&0)));
+            try!(write!(&mut stats, ",{}", *en.get(&motif_id).unwrap_or(&0)));
         }
-        try!(writeln!(&mut stats, ""));
+        try!(writeln!(&mut stats, ",{},{},{},{},{}", s.mean, s.sd, s.z_score, s.p_value, s.significance));
     }
-    Ok(())
+    stats.flush()
+}
+
+fn write_instances<W: Write, D: Directedness>(mut tsv: W, net: &Network<D>, instances: &[MotifInstance])
+                                              -> io::Result<()> {
+    try!(write!(&mut tsv, "MotifId"));
+    for i in 0..instances.first().map(|i| i.nodes.len()).unwrap_or(0) {
+        try!(write!(&mut tsv, "\tNode{}", i + 1));
+    }
+    try!(writeln!(&mut tsv, ""));
+    for instance in instances {
+        try!(write!(&mut tsv, "{}", instance.id));
+        for node in &instance.nodes {
+            try!(write!(&mut tsv, "\t{}", net[*node]));
+        }
+        try!(writeln!(&mut tsv, ""));
+    }
+    tsv.flush()
+}
+
+fn write_orbits<W: Write, D: Directedness>(mut tsv: W, net: &Network<D>, orbits: &OrbitCounts) -> io::Result<()> {
+    try!(write!(&mut tsv, "Node"));
+    for &(ref id, orbit) in &orbits.orbits {
+        try!(write!(&mut tsv, "\t{}:{}", id, orbit));
+    }
+    try!(writeln!(&mut tsv, ""));
+    for (node, counts) in net.node_indices().zip(&orbits.counts) {
+        try!(write!(&mut tsv, "{}", net[node]));
+        for count in counts {
+            try!(write!(&mut tsv, "\t{}", count));
+        }
+        try!(writeln!(&mut tsv, ""));
+    }
+    tsv.flush()
 }
@@ -1,63 +1,331 @@
+use canon::*;
+use csr::*;
+use error::*;
 use nauty::*;
 use network::*;
+use randomize::Rng;
 
+use arrayvec::ArrayVec;
 pub use fixedbitset::FixedBitSet;
-use std::collections::{BTreeMap, BTreeSet};
+use std::cmp;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
 use std::iter::FromIterator;
+use std::mem;
+use std::result;
+use std::str::FromStr;
+use std::sync::atomic;
+use std::sync::atomic::AtomicUsize;
+use std::thread;
+
+/// The narrowest `MotifId` digit, so networks of activations and repressions have base 4 IDs.
+pub const MIN_DIGIT_BITS: usize = 2;
+const WORD_BITS: usize = 64;
+
+/// The bits per `MotifId` digit for networks with `colours` edge colours: the smallest power of
+/// two that holds them, and at least `MIN_DIGIT_BITS`. Being a power of two, digits never
+/// straddle words.
+pub fn digit_bits(colours: usize) -> usize {
+    cmp::max(MIN_DIGIT_BITS, colours).next_power_of_two()
+}
+
+/// Identifies a motif by its adjacency matrix read as a number in base `2^bits`, where the
+/// `EdgeType` of `i -> j` is the digit of weight `2^(bits * (n * i + j))`. `bits` comes from
+/// `digit_bits`, so the base follows the number of edge colours, and IDs are only comparable
+/// between networks with the same `digit_bits`.
+///
+/// Undirected motifs only have digits for the cells above the diagonal, row by row, so the edge
+/// between `i < j` is at `digit_position(n, i, j, false)`.
+///
+/// A motif with node colours has `n` more digits after the adjacency digits, the `NodeColour` of
+/// node `i` at `adjacency_digits(n, directed) + i`. Uncoloured motifs, and those with every node
+/// colour 0, have none.
+///
+/// The number is stored as little-endian 64-bit words without trailing zero words, so it is exact
+/// for any motif size. It prints and parses as a plain decimal number.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct MotifId {
+    words: Vec<u64>,
+}
 
-pub type MotifId = u64;
-pub const MOTIF_BASE: u64 = 4;
 pub type MotifFreq = BTreeMap<MotifId, usize>;
 
-pub fn all_motifs(k: usize, net: &Network) -> BTreeMap<MotifId, usize> {
+impl MotifId {
+    pub fn new() -> MotifId {
+        MotifId { words: Vec::new() }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// The digit of weight `2^(bits * pos)`.
+    pub fn digit(&self, pos: usize, bits: usize) -> u64 {
+        let bit = pos * bits;
+        match self.words.get(bit / WORD_BITS) {
+            Some(w) => (w >> (bit % WORD_BITS)) & digit_mask(bits),
+            None => 0,
+        }
+    }
+
+    pub fn set_digit(&mut self, pos: usize, bits: usize, digit: u64) {
+        debug_assert!(digit <= digit_mask(bits), "digit out of range: {}", digit);
+        let bit = pos * bits;
+        let word = bit / WORD_BITS;
+        if word >= self.words.len() {
+            if digit == 0 {
+                return;
+            }
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] &= !(digit_mask(bits) << (bit % WORD_BITS));
+        self.words[word] |= digit << (bit % WORD_BITS);
+        self.trim();
+    }
+
+    pub fn from_str_radix(s: &str, radix: u32) -> result::Result<MotifId, ParseMotifIdError> {
+        if s.is_empty() {
+            return Err(ParseMotifIdError);
+        }
+        let mut id = MotifId::new();
+        for c in s.chars() {
+            let d = try!(c.to_digit(radix).ok_or(ParseMotifIdError));
+            id.mul_add(radix as u64, d as u64);
+        }
+        Ok(id)
+    }
+
+    fn trim(&mut self) {
+        while self.words.last() == Some(&0) {
+            self.words.pop();
+        }
+    }
+
+    fn mul_add(&mut self, mul: u64, add: u64) {
+        let mut carry = add as u128;
+        for w in &mut self.words {
+            let v = *w as u128 * mul as u128 + carry;
+            *w = v as u64;
+            carry = v >> WORD_BITS;
+        }
+        if carry != 0 {
+            self.words.push(carry as u64);
+        }
+        self.trim();
+    }
+
+    fn div_rem(&mut self, div: u64) -> u64 {
+        let mut rem = 0u128;
+        for w in self.words.iter_mut().rev() {
+            let v = (rem << WORD_BITS) | *w as u128;
+            *w = (v / div as u128) as u64;
+            rem = v % div as u128;
+        }
+        self.trim();
+        rem as u64
+    }
+}
+
+fn digit_mask(bits: usize) -> u64 {
+    (1 << bits) - 1
+}
+
+impl From<u64> for MotifId {
+    fn from(v: u64) -> MotifId {
+        let mut id = MotifId { words: vec![v] };
+        id.trim();
+        id
+    }
+}
+
+impl Ord for MotifId {
+    fn cmp(&self, other: &MotifId) -> Ordering {
+        self.words.len().cmp(&other.words.len())
+            .then_with(|| self.words.iter().rev().cmp(other.words.iter().rev()))
+    }
+}
+
+impl PartialOrd for MotifId {
+    fn partial_cmp(&self, other: &MotifId) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for MotifId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const CHUNK: u64 = 10_000_000_000_000_000_000;
+        let mut n = self.clone();
+        let mut chunks = vec![n.div_rem(CHUNK)];
+        while !n.is_zero() {
+            chunks.push(n.div_rem(CHUNK));
+        }
+        try!(write!(f, "{}", chunks.pop().unwrap()));
+        for c in chunks.iter().rev() {
+            try!(write!(f, "{:019}", c));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for MotifId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseMotifIdError;
+
+impl fmt::Display for ParseMotifIdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid motif id")
+    }
+}
+
+impl FromStr for MotifId {
+    type Err = ParseMotifIdError;
+    fn from_str(s: &str) -> result::Result<MotifId, ParseMotifIdError> {
+        MotifId::from_str_radix(s, 10)
+    }
+}
+
+/// Fails with `InvalidSize` unless `1 <= k <= MAX_K`.
+pub fn check_size(k: usize) -> Result<()> {
+    if k < 1 || k > MAX_K {
+        return Err(Error::InvalidSize { k: k, max: MAX_K });
+    }
+    Ok(())
+}
+
+/// Fails with `InvalidEdgeType` if an edge of `net` has no colour, or more than a `bits` wide
+/// `MotifId` digit holds.
+pub fn check_edge_types<D: Directedness>(net: &Network<D>, bits: usize) -> Result<()> {
+    match net.raw_edges().iter().find(|e| e.weight == 0 || e.weight as u64 > digit_mask(bits)) {
+        Some(e) => Err(Error::InvalidEdgeType(e.weight)),
+        None => Ok(()),
+    }
+}
+
+/// Fails with `NodeColourCount` unless `colours` is empty or has one colour per node of `net`,
+/// and with `InvalidNodeColour` if one is more than a `bits` wide `MotifId` digit holds.
+pub fn check_node_colours<D: Directedness>(net: &Network<D>, colours: &[NodeColour], bits: usize) -> Result<()> {
+    if !colours.is_empty() && colours.len() != net.node_count() {
+        return Err(Error::NodeColourCount { nodes: net.node_count(), colours: colours.len() });
+    }
+    match colours.iter().find(|c| **c as u64 > digit_mask(bits)) {
+        Some(c) => Err(Error::InvalidNodeColour(*c)),
+        None => Ok(()),
+    }
+}
+
+/// The `MotifId` digit width for `net`.
+fn net_digit_bits<D: Directedness>(net: &Network<D>) -> usize {
+    digit_bits(colour_count(net))
+}
+
+/// The `MotifId` digit width for `net` with node colours `colours`, wide enough for both.
+pub fn coloured_digit_bits<D: Directedness>(net: &Network<D>, colours: &[NodeColour]) -> usize {
+    let all = colours.iter().fold(0, |all, c| all | c);
+    digit_bits(cmp::max(colour_count(net), mem::size_of::<NodeColour>() * 8 - all.leading_zeros() as usize))
+}
+
+pub fn all_motifs<D: Directedness>(k: usize, net: &Network<D>) -> Result<MotifFreq> {
+    try!(check_size(k));
+    let bits = net_digit_bits(net);
     let mut hash = BTreeSet::new();
     let mut out = BTreeMap::new();
     for e in net.raw_edges() {
         let mut s = BTreeSet::new();
         s.insert(e.source());
         s.insert(e.target());
-        search_subset(k, net, &mut s, &mut hash, &mut out);
+        try!(search_subset(k, bits, net, &mut s, &mut hash, &mut out));
+    }
+    Ok(out)
+}
+
+/// The number of adjacency digits in the `MotifId` of an n-node motif: the whole matrix if it is
+/// directed, or the cells above the diagonal if not.
+pub fn adjacency_digits(n: usize, directed: bool) -> usize {
+    if directed { n * n } else { n * n.saturating_sub(1) / 2 }
+}
+
+/// The position of the `MotifId` digit for the edge from node `i` to node `j` of an n-node motif.
+/// Undirected motifs share one digit between `i - j` and `j - i`, and have none for self-loops.
+pub fn digit_position(n: usize, i: usize, j: usize, directed: bool) -> Option<usize> {
+    if directed {
+        Some(n * i + j)
+    } else if i == j {
+        None
+    } else {
+        let (i, j) = (cmp::min(i, j), cmp::max(i, j));
+        // Rows 0 to i - 1 hold n - 1, n - 2, ... cells.
+        Some(i * (2 * n - i - 1) / 2 + j - i - 1)
     }
-    out
 }
 
-pub fn motif_id(motif: &Network) -> MotifId {
-    let mut id = 0;
-    let n = motif.node_count() as u32;
+/// The `MotifId` of `motif` as it is, with `bits` wide digits.
+pub fn motif_id<D: Directedness>(motif: &Network<D>, bits: usize) -> Result<MotifId> {
+    try!(check_edge_types(motif, bits));
+    let mut id = MotifId::new();
+    let n = motif.node_count();
     for e in motif.raw_edges() {
-        let edge_id = MOTIF_BASE.pow(n * e.source().index() as u32 + e.target().index() as u32) *
-                      e.weight as u64;
-        id += edge_id;
+        if let Some(pos) = digit_position(n, e.source().index(), e.target().index(), D::is_directed()) {
+            let digit = id.digit(pos, bits) | e.weight as u64;
+            id.set_digit(pos, bits, digit);
+        }
     }
-    id
+    Ok(id)
+}
+
+/// The `MotifId` of `motif` as it is, with node `i` of colour `colours[i]`.
+pub fn coloured_motif_id<D: Directedness>(motif: &Network<D>, colours: &[NodeColour], bits: usize)
+                                          -> Result<MotifId> {
+    try!(check_node_colours(motif, colours, bits));
+    let mut id = try!(motif_id(motif, bits));
+    let start = adjacency_digits(motif.node_count(), D::is_directed());
+    for (i, c) in colours.iter().enumerate() {
+        id.set_digit(start + i, bits, *c as u64);
+    }
+    Ok(id)
+}
+
+/// The node colours of the n-node motif with `MotifId` `id`, all 0 if it has none.
+pub fn id_node_colours<D: Directedness>(n: usize, bits: usize, id: &MotifId) -> Vec<NodeColour> {
+    let start = adjacency_digits(n, D::is_directed());
+    (0..n).map(|i| id.digit(start + i, bits) as NodeColour).collect()
 }
 
-pub fn id_to_network(n: usize, mut id: MotifId) -> Network {
+/// The n-node network with `MotifId` `id` in `bits` wide digits, leaving out any node colours.
+pub fn id_to_network<D: Directedness>(n: usize, bits: usize, id: &MotifId) -> Network<D> {
     let mut net = Network::with_capacity(n, 0);
     for _ in 0..n {
         net.add_node(String::new());
     }
-    for i in (0..n).map(NodeIndex::new) {
-        for j in (0..n).map(NodeIndex::new) {
-            let e = id % MOTIF_BASE;
-            id /= MOTIF_BASE;
+    for i in 0..n {
+        // Undirected edges are only above the diagonal.
+        let first = if D::is_directed() { 0 } else { i + 1 };
+        for j in first..n {
+            let e = id.digit(digit_position(n, i, j, D::is_directed()).unwrap(), bits);
             if e != 0 {
-                net.add_edge(i, j, e as EdgeType);
+                net.add_edge(NodeIndex::new(i), NodeIndex::new(j), e as EdgeType);
             }
         }
     }
     net
 }
 
-fn search_subset(k: usize,
-                 net: &Network,
-                 s: &mut BTreeSet<NodeIndex>,
-                 hash: &mut BTreeSet<BTreeSet<NodeIndex>>,
-                 out: &mut BTreeMap<MotifId, usize>) {
+fn search_subset<D: Directedness>(k: usize,
+                                  bits: usize,
+                                  net: &Network<D>,
+                                  s: &mut BTreeSet<NodeIndex>,
+                                  hash: &mut BTreeSet<BTreeSet<NodeIndex>>,
+                                  out: &mut MotifFreq)
+                                  -> Result<()> {
     if s.len() == k && !hash.contains(s) {
         hash.insert(s.clone());
-        let motif = canonicalize(net.subnet(&Vec::from_iter(s.iter().cloned())));
-        let motif_id = motif_id(&motif);
+        let motif = try!(canonicalize(try!(net.subnet(&Vec::from_iter(s.iter().cloned())))));
+        let motif_id = try!(motif_id(&motif, bits));
         *out.entry(motif_id).or_insert(0) += 1;
     } else if s.len() < k {
         hash.insert(s.clone());
@@ -66,55 +334,504 @@ fn search_subset(k: usize,
                 if !s.contains(&v) {
                     s.insert(v);
                     if !hash.contains(s) {
-                        search_subset(k, net, s, hash, out);
+                        try!(search_subset(k, bits, net, s, hash, out));
                     }
                     s.remove(&v);
                 }
             }
         }
     }
+    Ok(())
 }
 
-pub fn enumerate_subgraphs(k: usize, net: &Network) -> BTreeMap<MotifId, usize> {
-    let mut out = BTreeMap::new();
-    let n = net.node_count();
-    for v in (0..n).map(NodeIndex::new) {
-        let v_subgraph = vec![v].into_iter().collect();
-        let v_subgraph_neighbours = net.neighbors_undirected(v).collect();
-        let v_extension = net.neighbors_undirected(v).filter(|u| *u > v).collect();
-        extend_subgraph(k, net, v, v_subgraph, v_subgraph_neighbours, v_extension, &mut out);
-    }
-    out
-}
-
-pub fn extend_subgraph(k: usize,
-                       net: &Network,
-                       v: NodeIndex,
-                       v_subgraph: BTreeSet<NodeIndex>,
-                       v_subgraph_neighbours: BTreeSet<NodeIndex>,
-                       mut v_extension: BTreeSet<NodeIndex>,
-                       out: &mut BTreeMap<MotifId, usize>) {
-    if v_subgraph.len() == k {
-        let motif = canonicalize(net.subnet(Vec::from_iter(v_subgraph)));
-        *out.entry(motif_id(&motif)).or_insert(0) += 1;
-    } else {
-        while let Some(w) = {
-            let maybe_w = v_extension.iter().cloned().next();
-            maybe_w.map(|w| v_extension.remove(&w));
-            maybe_w
-        } {
-            let w_neighbours: BTreeSet<_> = net.neighbors_undirected(w).filter(|u| u > &v).collect();
-            let v_extension_prime = &v_extension | &(&w_neighbours - &v_subgraph_neighbours);
-            extend_subgraph(
-                k,
-                net,
-                v,
-                &v_subgraph | &BTreeSet::from_iter(vec![w]),
-                &v_subgraph_neighbours | &BTreeSet::from_iter(net.neighbors_undirected(w)),
-                v_extension_prime,
-                out
-            )
+/// Counts the k-subgraphs of `net` by motif, with `MotifId` digits as wide as `net`'s colours
+/// need.
+pub fn enumerate_subgraphs<D: Directedness>(k: usize, net: &Network<D>) -> Result<MotifFreq> {
+    enumerate_coloured_subgraphs(k, net, &[])
+}
+
+/// `enumerate_subgraphs` where node `v` of `net` has colour `colours[v]`, so that subgraphs of
+/// the same shape are different motifs if their nodes' colours differ. The digits are wide enough
+/// for the node colours as well as the edge colours.
+pub fn enumerate_coloured_subgraphs<D: Directedness>(k: usize, net: &Network<D>, colours: &[NodeColour]) -> Result<MotifFreq> {
+    enumerate_subgraphs_with(net, colours, &mut try!(CanonCache::<D>::new(k, coloured_digit_bits(net, colours))))
+}
+
+/// `enumerate_coloured_subgraphs` for `cache.k()` and `cache.bits()`, reusing and filling
+/// `cache`. `colours` may be empty.
+pub fn enumerate_subgraphs_with<D: Directedness>(net: &Network<D>,
+                                                 colours: &[NodeColour],
+                                                 cache: &mut CanonCache<D>)
+                                                 -> Result<MotifFreq> {
+    try!(check_edge_types(net, cache.bits()));
+    try!(check_node_colours(net, colours, cache.bits()));
+    let csr = Csr::new(net);
+    let mut counts = RawCounts::default();
+    {
+        let mut esu = Esu::new(&csr, cache.k(), cache.bits(), D::is_directed(), |_: &[u32], raw: &MotifId| counts.add(raw));
+        esu.colours = colours;
+        for v in 0..csr.node_count() {
+            esu.root(v as u32);
+        }
+    }
+    Ok(counts.canonical(cache))
+}
+
+/// One occurrence of a motif in a network.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MotifInstance {
+    pub id: MotifId,
+    /// The network's nodes in the motif's canonical order, so `nodes[i]` plays the part of node
+    /// `i` of `id_to_network(k, bits, &id)`.
+    pub nodes: Vec<NodeIndex>,
+}
+
+/// Every occurrence in `net` of the k-node motifs in `ids`, which have digits as wide as `net`'s
+/// colours need.
+pub fn motif_instances<D: Directedness>(k: usize, net: &Network<D>, ids: &BTreeSet<MotifId>) -> Result<Vec<MotifInstance>> {
+    let bits = net_digit_bits(net);
+    let mut cache = try!(CanonCache::<D>::new(k, bits));
+    try!(check_edge_types(net, bits));
+    let csr = Csr::new(net);
+    // For each raw adjacency code, its motif and canonical order if it is one of `ids`.
+    let mut labellings = HashMap::new();
+    let mut instances = Vec::new();
+    {
+        let mut esu = Esu::new(&csr, k, bits, D::is_directed(), |subgraph: &[u32], raw: &MotifId| {
+            if !labellings.contains_key(raw) {
+                let id = cache.get(raw.clone());
+                let labelling = if ids.contains(&id) {
+                    Some((id, canonical_labelling(&id_to_network::<D>(k, bits, raw)).expect(CHECKED)))
+                } else {
+                    None
+                };
+                labellings.insert(raw.clone(), labelling);
+            }
+            if let Some((ref id, ref lab)) = labellings[raw] {
+                instances.push(MotifInstance {
+                    id: id.clone(),
+                    nodes: lab.iter().map(|i| NodeIndex::new(subgraph[i.index()] as usize)).collect(),
+                });
+            }
+        });
+        for v in 0..csr.node_count() {
+            esu.root(v as u32);
+        }
+    }
+    Ok(instances)
+}
+
+/// How often each node of a network takes each automorphism orbit of each k-node motif: its
+/// graphlet degree vector.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrbitCounts {
+    /// The orbits, as a motif and the smallest node of `id_to_network(k, bits, &id)` in the orbit.
+    pub orbits: Vec<(MotifId, usize)>,
+    /// `counts[v][i]` is the number of occurrences of `orbits[i].0` with node `v` in `orbits[i]`.
+    pub counts: Vec<Vec<usize>>,
+}
+
+pub fn orbit_counts<D: Directedness>(k: usize, net: &Network<D>) -> Result<OrbitCounts> {
+    let bits = net_digit_bits(net);
+    let mut cache = try!(CanonCache::<D>::new(k, bits));
+    try!(check_edge_types(net, bits));
+    let csr = Csr::new(net);
+    // For each raw adjacency code, the column of each position in it.
+    let mut columns: HashMap<MotifId, Vec<usize>> = HashMap::new();
+    let mut orbits = Vec::new();
+    let mut orbit_columns = HashMap::new();
+    let mut counts = vec![Vec::new(); net.node_count()];
+    {
+        let mut esu = Esu::new(&csr, k, bits, D::is_directed(), |subgraph: &[u32], raw: &MotifId| {
+            if !columns.contains_key(raw) {
+                let id = cache.get(raw.clone());
+                let (lab, raw_orbits) = canonical_orbits(&id_to_network::<D>(k, bits, raw)).expect(CHECKED);
+                let mut raw_columns = vec![0; k];
+                for (i, p) in lab.iter().enumerate() {
+                    // Name the orbit by the first canonical position in it.
+                    let first = lab.iter()
+                        .position(|q| raw_orbits[q.index()] == raw_orbits[p.index()])
+                        .unwrap();
+                    debug_assert!(first <= i);
+                    let column = orbit_columns.entry((id.clone(), first)).or_insert_with(|| {
+                        orbits.push((id.clone(), first));
+                        orbits.len() - 1
+                    });
+                    raw_columns[p.index()] = *column;
+                }
+                columns.insert(raw.clone(), raw_columns);
+            }
+            for (v, column) in subgraph.iter().zip(&columns[raw]) {
+                let node_counts = &mut counts[*v as usize];
+                if node_counts.len() <= *column {
+                    node_counts.resize(column + 1, 0);
+                }
+                node_counts[*column] += 1;
+            }
+        });
+        for v in 0..csr.node_count() {
+            esu.root(v as u32);
+        }
+    }
+
+    // Put the columns in order of orbit.
+    let mut order = Vec::from_iter(0..orbits.len());
+    order.sort_by(|a, b| orbits[*a].cmp(&orbits[*b]));
+    Ok(OrbitCounts {
+        orbits: order.iter().map(|i| orbits[*i].clone()).collect(),
+        counts: counts.iter()
+            .map(|c| order.iter().map(|i| *c.get(*i).unwrap_or(&0)).collect())
+            .collect(),
+    })
+}
+
+/// Motif counts from sampling k-subgraphs with RAND-ESU.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MotifSample {
+    /// How many of the sampled subgraphs are of each motif.
+    pub sampled: MotifFreq,
+    /// The chance that any one k-subgraph is sampled: the product of the probabilities per depth.
+    pub probability: f64,
+}
+
+impl MotifSample {
+    pub fn total(&self) -> usize {
+        self.sampled.values().sum()
+    }
+
+    /// The estimated fraction of k-subgraphs that are of each motif.
+    pub fn concentrations(&self) -> BTreeMap<MotifId, f64> {
+        let total = self.total() as f64;
+        self.sampled.iter().map(|(id, count)| (id.clone(), *count as f64 / total)).collect()
+    }
+
+    /// Unbiased estimates of each motif's count in the network.
+    pub fn estimated_counts(&self) -> BTreeMap<MotifId, f64> {
+        self.sampled.iter().map(|(id, count)| (id.clone(), *count as f64 / self.probability)).collect()
+    }
+
+    /// The variance of each of `estimated_counts`, if every subgraph were sampled independently.
+    /// Subgraphs sharing nodes are sampled together more often than that, so this is an
+    /// underestimate on dense networks.
+    pub fn variances(&self) -> BTreeMap<MotifId, f64> {
+        let p = self.probability;
+        self.sampled.iter().map(|(id, count)| (id.clone(), *count as f64 * (1.0 - p) / (p * p))).collect()
+    }
+}
+
+/// Samples the k-subgraphs of `net` with RAND-ESU (Wernicke 2006): ESU where each node at depth
+/// `d` of the search tree, from the root node at depth 0, is only visited with probability
+/// `probabilities[d]`.
+///
+/// Each k-subgraph is sampled with the same probability, so the result is an unbiased sample.
+/// The same `seed` gives the same sample.
+pub fn sample_subgraphs<D: Directedness>(k: usize, net: &Network<D>, probabilities: &[f64], seed: u64) -> Result<MotifSample> {
+    let bits = net_digit_bits(net);
+    let mut cache = try!(CanonCache::<D>::new(k, bits));
+    try!(check_edge_types(net, bits));
+    if probabilities.len() != k || !probabilities.iter().all(|p| *p > 0.0 && *p <= 1.0) {
+        return Err(Error::InvalidProbabilities(probabilities.to_vec()));
+    }
+    let csr = Csr::new(net);
+    let mut counts = RawCounts::default();
+    {
+        let mut esu = Esu::new(&csr, k, bits, D::is_directed(), |_: &[u32], raw: &MotifId| counts.add(raw));
+        esu.sampling = Some((probabilities.to_vec(), Rng::new(seed)));
+        for v in 0..csr.node_count() {
+            esu.root(v as u32);
+        }
+    }
+    Ok(MotifSample {
+        sampled: counts.canonical(&mut cache),
+        probability: probabilities.iter().product(),
+    })
+}
+
+/// The number of threads to use when none is asked for.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+pub fn merge_freq(into: &mut MotifFreq, from: MotifFreq) {
+    for (id, count) in from {
+        *into.entry(id).or_insert(0) += count;
+    }
+}
+
+/// `enumerate_coloured_subgraphs` with the root nodes dealt out round-robin to `threads`
+/// threads, each counting into its own `MotifFreq`. `colours` may be empty.
+pub fn enumerate_subgraphs_parallel<D: Directedness>(k: usize,
+                                                     net: &Network<D>,
+                                                     colours: &[NodeColour],
+                                                     threads: usize)
+                                                     -> Result<MotifFreq> {
+    let cache = try!(CanonCache::<D>::new(k, coloured_digit_bits(net, colours)));
+    enumerate_subgraphs_parallel_with(net, colours, &cache, threads)
+}
+
+/// `enumerate_subgraphs_parallel` for `cache.k()` and `cache.bits()`, each thread starting from a
+/// copy of `cache`.
+pub fn enumerate_subgraphs_parallel_with<D: Directedness>(net: &Network<D>,
+                                                          colours: &[NodeColour],
+                                                          cache: &CanonCache<D>,
+                                                          threads: usize)
+                                                          -> Result<MotifFreq> {
+    let threads = cmp::max(1, cmp::min(threads, net.node_count()));
+    if threads == 1 {
+        return enumerate_subgraphs_with(net, colours, &mut cache.clone());
+    }
+    let (k, bits) = (cache.k(), cache.bits());
+    try!(check_edge_types(net, bits));
+    try!(check_node_colours(net, colours, bits));
+    let csr = &Csr::new(net);
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads).map(|t| {
+            scope.spawn(move || {
+                let mut counts = RawCounts::default();
+                {
+                    let mut esu = Esu::new(csr, k, bits, D::is_directed(), |_: &[u32], raw: &MotifId| counts.add(raw));
+                    esu.colours = colours;
+                    for v in (t..csr.node_count()).step_by(threads) {
+                        esu.root(v as u32);
+                    }
+                }
+                counts.canonical(&mut cache.clone())
+            })
+        }).collect();
+        let mut out = BTreeMap::new();
+        for worker in workers {
+            merge_freq(&mut out, worker.join().unwrap());
         }
+        Ok(out)
+    })
+}
+
+/// Counts the k-subgraphs of each network in `nets`, with up to `threads` networks counted at
+/// once. The `MotifId` digits are as wide as the most colourful network needs, so the counts are
+/// all comparable.
+///
+/// `colours[i]` are the node colours of `nets[i]`. `colours` is either empty, for no colours, or
+/// has an entry for each network.
+///
+/// Fails on the first network, in order, that cannot be counted.
+pub fn enumerate_ensemble<D: Directedness + Sync>(k: usize,
+                                                  nets: &[Network<D>],
+                                                  colours: &[Vec<NodeColour>],
+                                                  threads: usize)
+                                                  -> Result<Vec<MotifFreq>> {
+    let colour = |i: usize| colours.get(i).map_or(&[][..], |c| &c[..]);
+    let bits = nets.iter().enumerate()
+        .map(|(i, net)| coloured_digit_bits(net, colour(i)))
+        .max()
+        .unwrap_or(MIN_DIGIT_BITS);
+    enumerate_ensemble_with(nets, colours, &try!(CanonCache::<D>::new(k, bits)), threads)
+}
+
+/// `enumerate_ensemble` for `cache.k()` and `cache.bits()`, each thread starting from a copy of
+/// `cache`. Counting the original network with the same cache makes its ids comparable with the
+/// ensemble's, even if the ensemble needs narrower digits.
+pub fn enumerate_ensemble_with<D: Directedness + Sync>(nets: &[Network<D>],
+                                                       colours: &[Vec<NodeColour>],
+                                                       cache: &CanonCache<D>,
+                                                       threads: usize)
+                                                       -> Result<Vec<MotifFreq>> {
+    if !colours.is_empty() && colours.len() != nets.len() {
+        return Err(Error::NodeColourCount { nodes: nets.len(), colours: colours.len() });
+    }
+    let colours = |i: usize| colours.get(i).map_or(&[][..], |c| &c[..]);
+    let threads = cmp::max(1, cmp::min(threads, nets.len()));
+    let next = AtomicUsize::new(0);
+    let mut out = Vec::from_iter((0..nets.len()).map(|_| Ok(MotifFreq::new())));
+    let counted: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads).map(|_| {
+            scope.spawn(|| {
+                let mut cache = cache.clone();
+                let mut counted = Vec::new();
+                loop {
+                    let i = next.fetch_add(1, atomic::Ordering::SeqCst);
+                    match nets.get(i) {
+                        Some(net) => counted.push((i, enumerate_subgraphs_with(net, colours(i), &mut cache))),
+                        None => return counted,
+                    }
+                }
+            })
+        }).collect();
+        workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect()
+    });
+    for (i, freq) in counted {
+        out[i] = freq;
+    }
+    out.into_iter().collect()
+}
+
+/// The largest k that `enumerate_subgraphs` supports.
+pub const MAX_K: usize = 32;
+
+/// For canonicalizing motifs once `check_size` and `check_edge_types` have passed: `MAX_K` nodes
+/// always fit in nauty.
+const CHECKED: &'static str = "motif size and edge types were checked";
+
+/// The ESU algorithm (Wernicke 2006) over a `Csr`: every connected k-subgraph is found exactly
+/// once, from its smallest node.
+///
+/// Each k-subgraph is passed to `visit` with its raw adjacency code.
+struct Esu<'a, F> {
+    csr: &'a Csr,
+    k: usize,
+    /// `MotifId` digit width.
+    bits: usize,
+    /// Whether the raw codes have the whole adjacency matrix or only its upper triangle.
+    directed: bool,
+    subgraph: ArrayVec<u32, MAX_K>,
+    /// The nodes in or next to `subgraph`.
+    covered: FixedBitSet,
+    /// Nodes newly covered by each node of `subgraph`, to undo when it is removed.
+    covered_stack: Vec<u32>,
+    /// The extension set at each depth, kept to reuse their allocations.
+    extensions: Vec<Vec<u32>>,
+    raw: MotifId,
+    visit: F,
+    /// The colour of each node, or empty for none.
+    colours: &'a [NodeColour],
+    /// For RAND-ESU, the chance of visiting a node at each depth, and the dice.
+    sampling: Option<(Vec<f64>, Rng)>,
+}
+
+impl<'a, F: FnMut(&[u32], &MotifId)> Esu<'a, F> {
+    fn new(csr: &'a Csr, k: usize, bits: usize, directed: bool, visit: F) -> Esu<'a, F> {
+        assert!(k <= MAX_K, "k greater than MAX_K ({}): {}", MAX_K, k);
+        Esu {
+            csr: csr,
+            k: k,
+            bits: bits,
+            directed: directed,
+            subgraph: ArrayVec::new(),
+            covered: FixedBitSet::with_capacity(csr.node_count()),
+            covered_stack: Vec::new(),
+            extensions: vec![Vec::new(); k],
+            raw: MotifId::new(),
+            visit: visit,
+            colours: &[],
+            sampling: None,
+        }
+    }
+
+    /// Whether to visit a node at `depth`.
+    fn keep(&mut self, depth: usize) -> bool {
+        match self.sampling {
+            Some((ref probabilities, ref mut rng)) => rng.next_f64() < probabilities[depth],
+            None => true,
+        }
+    }
+
+    /// Counts the k-subgraphs whose smallest node is `v`.
+    fn root(&mut self, v: u32) {
+        if self.k == 0 || !self.keep(0) {
+            return;
+        }
+        self.extensions[0].clear();
+        self.extensions[0].extend(self.csr.neighbours(v as usize).iter().filter(|&&u| u > v));
+        self.push(v);
+        if self.k == 1 {
+            self.count();
+        } else {
+            self.extend(0, v);
+        }
+        self.pop(0);
+    }
+
+    fn extend(&mut self, depth: usize, v: u32) {
+        let mut i = 0;
+        while i < self.extensions[depth].len() {
+            let w = self.extensions[depth][i];
+            i += 1;
+            if !self.keep(self.subgraph.len()) {
+                continue;
+            }
+            let mark = self.covered_stack.len();
+            if self.subgraph.len() + 1 == self.k {
+                self.subgraph.push(w);
+                self.count();
+                self.subgraph.pop();
+                continue;
+            }
+            {
+                // W's exclusive neighbours: above v, and neither in nor next to the subgraph.
+                let (lower, upper) = self.extensions.split_at_mut(depth + 1);
+                let next = &mut upper[0];
+                let covered = &self.covered;
+                next.clear();
+                next.extend_from_slice(&lower[depth][i..]);
+                next.extend(self.csr.neighbours(w as usize).iter()
+                    .filter(|&&u| u > v && !covered[u as usize]));
+            }
+            self.push(w);
+            self.extend(depth + 1, v);
+            self.pop(mark);
+        }
+    }
+
+    fn push(&mut self, w: u32) {
+        self.subgraph.push(w);
+        for &u in Some(&w).into_iter().chain(self.csr.neighbours(w as usize)) {
+            if !self.covered.put(u as usize) {
+                self.covered_stack.push(u);
+            }
+        }
+    }
+
+    fn pop(&mut self, mark: usize) {
+        self.subgraph.pop();
+        for u in self.covered_stack.drain(mark..) {
+            self.covered.set(u as usize, false);
+        }
+    }
+
+    fn count(&mut self) {
+        let n = self.subgraph.len();
+        let adjacency = adjacency_digits(n, self.directed);
+        let digits = if self.colours.is_empty() { adjacency } else { adjacency + n };
+        self.raw.words.clear();
+        self.raw.words.resize((digits * self.bits + WORD_BITS - 1) / WORD_BITS, 0);
+        for (i, &u) in self.subgraph.iter().enumerate() {
+            // The `Csr` of an undirected network is symmetric, so the upper triangle is enough.
+            let first = if self.directed { 0 } else { i + 1 };
+            for (j, &v) in self.subgraph.iter().enumerate().skip(first) {
+                let bit = digit_position(n, i, j, self.directed).unwrap() * self.bits;
+                let e = self.csr.edge(u as usize, v as usize) as u64;
+                self.raw.words[bit / WORD_BITS] |= e << (bit % WORD_BITS);
+            }
+            if !self.colours.is_empty() {
+                let bit = (adjacency + i) * self.bits;
+                self.raw.words[bit / WORD_BITS] |= (self.colours[u as usize] as u64) << (bit % WORD_BITS);
+            }
+        }
+        self.raw.trim();
+        (self.visit)(&self.subgraph, &self.raw);
+    }
+}
+
+/// Occurrences counted by raw adjacency code, so each distinct code is only canonicalized once.
+#[derive(Default)]
+struct RawCounts(HashMap<MotifId, usize>);
+
+impl RawCounts {
+    fn add(&mut self, raw: &MotifId) {
+        // Only allocate a key for patterns not seen before.
+        match self.0.get_mut(raw) {
+            Some(count) => *count += 1,
+            None => {
+                self.0.insert(raw.clone(), 1);
+            }
+        }
+    }
+
+    fn canonical<D: Directedness>(self, cache: &mut CanonCache<D>) -> MotifFreq {
+        let mut out = BTreeMap::new();
+        for (raw, count) in self.0 {
+            *out.entry(cache.get(raw)).or_insert(0) += count;
+        }
+        out
     }
 }
 
@@ -130,105 +847,536 @@ fn test_shared_ff() {
         net.add_edge(source, node, 1);
         net.add_edge(node, sink, 1);
     }
-    assert_eq!(Some(&n), all_motifs(3, &net).get(&motif_str("011001000")));
+    assert_eq!(Some(&n), all_motifs(3, &net).unwrap().get(&motif_str("011001000")));
 }
 
 #[test]
 fn test_motifs_3() {
     let net = network_from_paper();
-    let motifs = all_motifs(3, &net);
+    let motifs = all_motifs(3, &net).unwrap();
 
-    let feedforward = motif_id(&canonical_subnet(&net, &[3, 12, 13]));
+    let feedforward = motif_id(&canonical_subnet(&net, &[3, 12, 13]), 2).unwrap();
     assert_eq!(Some(&5), motifs.get(&feedforward));
 
-    let line = motif_id(&canonical_subnet(&net, &[1, 2, 16]));
+    let line = motif_id(&canonical_subnet(&net, &[1, 2, 16]), 2).unwrap();
     assert_eq!(Some(&10), motifs.get(&line));
 
-    let twofan = motif_id(&canonical_subnet(&net, &[5, 10, 13]));
+    let twofan = motif_id(&canonical_subnet(&net, &[5, 10, 13]), 2).unwrap();
     assert_eq!(Some(&3), motifs.get(&twofan));
 
-    let vee = motif_id(&canonical_subnet(&net, &[3, 5, 13]));
+    let vee = motif_id(&canonical_subnet(&net, &[3, 5, 13]), 2).unwrap();
     assert_eq!(Some(&3), motifs.get(&vee));
 
     assert_eq!(4, motifs.len());
 
-    assert_eq!(enumerate_subgraphs(3, &net), motifs);
+    assert_eq!(enumerate_subgraphs(3, &net).unwrap(), motifs);
 }
 
 #[test]
 fn test_motifs_4() {
     let net = network_from_paper();
-    let motifs = all_motifs(4, &net);
+    let motifs = all_motifs(4, &net).unwrap();
 
-    let line = motif_id(&canonical_subnet(&net, &[1, 2, 15, 16]));
+    let line = motif_id(&canonical_subnet(&net, &[1, 2, 15, 16]), 2).unwrap();
     assert_eq!(Some(&5), motifs.get(&line));
 
-    let feedforwardin = motif_id(&canonical_subnet(&net, &[1, 14, 15, 16]));
+    let feedforwardin = motif_id(&canonical_subnet(&net, &[1, 14, 15, 16]), 2).unwrap();
     assert_eq!(Some(&2), motifs.get(&feedforwardin));
 
-    let feedforwardout = motif_id(&canonical_subnet(&net, &[1, 2, 8, 16]));
+    let feedforwardout = motif_id(&canonical_subnet(&net, &[1, 2, 8, 16]), 2).unwrap();
     assert_eq!(Some(&2), motifs.get(&feedforwardout));
 
-    let feedforwardsidein = motif_id(&canonical_subnet(&net, &[3, 5, 12, 13]));
+    let feedforwardsidein = motif_id(&canonical_subnet(&net, &[3, 5, 12, 13]), 2).unwrap();
     assert_eq!(Some(&3), motifs.get(&feedforwardsidein));
 
-    let feedforwardsideout = motif_id(&canonical_subnet(&net, &[5, 6, 9, 10]));
+    let feedforwardsideout = motif_id(&canonical_subnet(&net, &[5, 6, 9, 10]), 2).unwrap();
     assert_eq!(Some(&1), motifs.get(&feedforwardsideout));
 
-    let branch = motif_id(&canonical_subnet(&net, &[5, 6, 9, 13]));
+    let branch = motif_id(&canonical_subnet(&net, &[5, 6, 9, 13]), 2).unwrap();
     assert_eq!(Some(&5), motifs.get(&branch));
 
-    let n = motif_id(&canonical_subnet(&net, &[13, 10, 3, 5]));
+    let n = motif_id(&canonical_subnet(&net, &[13, 10, 3, 5]), 2).unwrap();
     assert_eq!(Some(&4), motifs.get(&n));
 
-    let feedforwardendin = motif_id(&canonical_subnet(&net, &[4, 5, 6, 10]));
+    let feedforwardendin = motif_id(&canonical_subnet(&net, &[4, 5, 6, 10]), 2).unwrap();
     assert_eq!(Some(&1), motifs.get(&feedforwardendin));
 
-    let branch_feedforward = motif_id(&canonical_subnet(&net, &[5, 6, 10, 13]));
+    let branch_feedforward = motif_id(&canonical_subnet(&net, &[5, 6, 10, 13]), 2).unwrap();
     assert_eq!(Some(&1), motifs.get(&branch_feedforward));
 
     assert_eq!(9, motifs.len());
-    assert_eq!(enumerate_subgraphs(4, &net), motifs);
+    assert_eq!(enumerate_subgraphs(4, &net).unwrap(), motifs);
+}
+
+#[test]
+fn test_enumerate_random() {
+    use randomize::Rng;
+    let mut rng = Rng::new(3);
+    for _ in 0..10 {
+        let mut net = Network::new();
+        let n = 12 + rng.below(10);
+        for i in 0..n {
+            net.add_node(format!("{}", i));
+        }
+        for _ in 0..2 * n {
+            let (u, v) = (NodeIndex::new(rng.below(n)), NodeIndex::new(rng.below(n)));
+            net.add_edge(u, v, 1 + rng.below(3) as EdgeType);
+        }
+        for k in 2..6 {
+            assert_eq!(all_motifs(k, &net).unwrap(), enumerate_subgraphs(k, &net).unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_sample_subgraphs() {
+    let net = network_from_paper();
+    for k in 3..5 {
+        let full = sample_subgraphs(k, &net, &vec![1.0; k], 0).unwrap();
+        assert_eq!(1.0, full.probability);
+        assert_eq!(enumerate_subgraphs(k, &net).unwrap(), full.sampled);
+        for (_, v) in full.variances() {
+            assert_eq!(0.0, v);
+        }
+    }
+
+    let mut net = Network::new();
+    let nodes = Vec::from_iter((0..200).map(|i| net.add_node(format!("{}", i))));
+    let mut rng = Rng::new(5);
+    for _ in 0..1000 {
+        let (u, v) = (rng.below(200), rng.below(200));
+        if u != v {
+            net.add_edge(nodes[u], nodes[v], 1);
+        }
+    }
+    let exact = enumerate_subgraphs(3, &net).unwrap();
+    let total = exact.values().sum::<usize>() as f64;
+    let probabilities = [1.0, 0.5, 0.5];
+    let sample = sample_subgraphs(3, &net, &probabilities, 1).unwrap();
+    assert_eq!(sample, sample_subgraphs(3, &net, &probabilities, 1).unwrap());
+    assert!(sample != sample_subgraphs(3, &net, &probabilities, 2).unwrap());
+    assert!((sample.total() as f64 - total / 4.0).abs() < 0.1 * total / 4.0);
+    let estimates = sample.estimated_counts();
+    let variances = sample.variances();
+    let concentrations = sample.concentrations();
+    for (id, count) in &exact {
+        if *count > 1000 {
+            // Within five standard deviations, with room for the variance being an underestimate.
+            assert!((estimates[id] - *count as f64).abs() < 5.0 * variances[id].sqrt() * 2.0);
+            assert!((concentrations[id] - *count as f64 / total).abs() < 0.05);
+        }
+    }
+}
+
+#[test]
+fn test_motif_instances() {
+    let net = network_from_paper();
+    let counts = enumerate_subgraphs(3, &net).unwrap();
+    let feedforward = motif_id(&canonical_subnet(&net, &[3, 12, 13]), 2).unwrap();
+    let line = motif_id(&canonical_subnet(&net, &[1, 2, 16]), 2).unwrap();
+    let ids = BTreeSet::from_iter(vec![feedforward.clone(), line.clone()]);
+    let instances = motif_instances(3, &net, &ids).unwrap();
+    assert_eq!(counts[&feedforward] + counts[&line], instances.len());
+    for instance in &instances {
+        // The nodes are in canonical order, so they give back the motif as is.
+        assert_eq!(instance.id, motif_id(&net.subnet(&instance.nodes).unwrap(), 2).unwrap());
+    }
+    // Node 5 regulates 6 and 10, and 6 regulates 10.
+    let ffl = instances.iter().find(|i| {
+        let mut names = Vec::from_iter(i.nodes.iter().map(|n| &net[*n][..]));
+        names.sort();
+        names == ["10", "5", "6"]
+    }).unwrap();
+    let motif: Network = id_to_network(3, 2, &feedforward);
+    let regulator = (0..3).map(NodeIndex::new)
+        .find(|n| motif.neighbors_directed(*n, ::petgraph::Outgoing).count() == 2)
+        .unwrap();
+    assert_eq!("5", net[ffl.nodes[regulator.index()]]);
+
+    assert!(motif_instances(3, &net, &BTreeSet::new()).unwrap().is_empty());
+}
+
+#[test]
+fn test_orbit_counts() {
+    let net = network_from_paper();
+    for k in 2..5 {
+        let orbits = orbit_counts(k, &net).unwrap();
+        let counts = enumerate_subgraphs(k, &net).unwrap();
+        // Every occurrence puts k nodes in orbits of its motif.
+        for (id, count) in &counts {
+            let total: usize = orbits.orbits.iter().enumerate()
+                .filter(|&(_, o)| o.0 == *id)
+                .map(|(i, _)| orbits.counts.iter().map(|c| c[i]).sum::<usize>())
+                .sum();
+            assert_eq!(k * count, total);
+        }
+        let mut sorted = orbits.orbits.clone();
+        sorted.sort();
+        assert_eq!(sorted, orbits.orbits);
+    }
+
+    // Nodes 3, 4 and 5 each regulate one feedforward loop. 13 is in one, but not on top.
+    let orbits = orbit_counts(3, &net).unwrap();
+    let feedforward = motif_id(&canonical_subnet(&net, &[3, 12, 13]), 2).unwrap();
+    let motif: Network = id_to_network(3, 2, &feedforward);
+    let regulator = (0..3).map(NodeIndex::new)
+        .find(|n| motif.neighbors_directed(*n, ::petgraph::Outgoing).count() == 2)
+        .unwrap();
+    let column = orbits.orbits.iter().position(|o| *o == (feedforward.clone(), regulator.index())).unwrap();
+    assert_eq!(1, orbits.counts[2][column]);
+    assert_eq!(1, orbits.counts[3][column]);
+    assert_eq!(1, orbits.counts[4][column]);
+    assert_eq!(0, orbits.counts[12][column]);
+}
+
+#[test]
+fn test_enumerate_parallel() {
+    let mut net = network_from_paper();
+    let extra = Vec::from_iter((0..8).map(|i| net.add_node(format!("x{}", i))));
+    for (i, u) in extra.iter().enumerate() {
+        net.add_edge(*u, NodeIndex::new(i), 2);
+        net.add_edge(NodeIndex::new(i + 3), *u, 3);
+    }
+    for k in 3..6 {
+        let sequential = enumerate_subgraphs(k, &net).unwrap();
+        for threads in 1..6 {
+            assert_eq!(sequential, enumerate_subgraphs_parallel(k, &net, &[], threads).unwrap());
+        }
+    }
+
+    let nets = vec![net.clone(), network_from_paper(), Network::new(), net];
+    let sequential = Vec::from_iter(nets.iter().map(|n| enumerate_subgraphs(4, n).unwrap()));
+    for threads in 1..6 {
+        assert_eq!(sequential, enumerate_ensemble(4, &nets, &[], threads).unwrap());
+    }
+}
+
+#[test]
+fn test_invalid_input() {
+    let mut net = network_from_paper();
+    match enumerate_subgraphs(MAX_K + 1, &net) {
+        Err(Error::InvalidSize { k, max: MAX_K }) => assert_eq!(MAX_K + 1, k),
+        r => panic!("expected InvalidSize, got {:?}", r),
+    }
+    assert!(enumerate_subgraphs_parallel(0, &net, &[], 4).is_err());
+    match sample_subgraphs(3, &net, &[1.0, 0.5], 0) {
+        Err(Error::InvalidProbabilities(_)) => {}
+        r => panic!("expected InvalidProbabilities, got {:?}", r),
+    }
+    assert!(sample_subgraphs(2, &net, &[1.0, 0.0], 0).is_err());
+
+    let (a, b) = (NodeIndex::new(0), NodeIndex::new(1));
+    net.add_edge(a, b, 4);
+    // A third colour does not fit in 2-bit digits.
+    assert!(motif_id(&net, 2).is_err());
+    net.add_edge(b, a, 0);
+    match enumerate_subgraphs(3, &net) {
+        Err(Error::InvalidEdgeType(0)) => {}
+        r => panic!("expected InvalidEdgeType, got {:?}", r),
+    }
+    assert!(orbit_counts(3, &net).is_err());
+    assert!(motif_instances(3, &net, &BTreeSet::new()).is_err());
+    assert!(enumerate_ensemble(3, &[network_from_paper(), net], &[], 2).is_err());
+    let nets = [network_from_paper(), network_from_paper()];
+    match enumerate_ensemble(3, &nets, &[vec![0; nets[0].node_count()]], 2) {
+        Err(Error::NodeColourCount { nodes: 2, colours: 1 }) => {}
+        r => panic!("expected NodeColourCount, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_enumerate_with_shared_cache() {
+    let net = network_from_paper();
+    let plain = vec![0; net.node_count()];
+    let mut colours = plain.clone();
+    colours[0] = 4;
+    let bits = coloured_digit_bits(&net, &colours);
+    assert!(bits > coloured_digit_bits(&net, &plain));
+    let cache = CanonCache::<Directed>::new(3, bits).unwrap();
+    let original = enumerate_subgraphs_parallel_with(&net, &colours, &cache, 3).unwrap();
+    assert_eq!(enumerate_coloured_subgraphs(3, &net, &colours).unwrap(), original);
+
+    // An ensemble that needs narrower digits still gets the original's.
+    let nets = vec![net.clone(), net.clone()];
+    let ensemble = enumerate_ensemble_with(&nets, &[plain.clone(), colours], &cache, 2).unwrap();
+    assert_eq!(original, ensemble[1]);
+    assert_eq!(enumerate_subgraphs_with(&net, &plain, &mut cache.clone()).unwrap(), ensemble[0]);
+    let plain = vec![plain];
+    let narrow = enumerate_ensemble_with(&nets[..1], &plain, &cache, 2).unwrap();
+    assert_eq!(&ensemble[..1], &narrow[..]);
+    assert!(narrow != enumerate_ensemble(3, &nets[..1], &plain, 2).unwrap());
+}
+
+#[test]
+fn test_edge_colours() {
+    assert_eq!(2, digit_bits(0));
+    assert_eq!(2, digit_bits(2));
+    assert_eq!(4, digit_bits(3));
+    assert_eq!(8, digit_bits(5));
+
+    // Colours 4 and 8 alongside activation and repression: 4-bit digits.
+    let mut net = network_from_paper();
+    let n = |i| NodeIndex::new(i);
+    net.add_edge(n(6), n(8), 4);
+    net.add_edge(n(8), n(10), 8);
+    net.add_edge(n(10), n(6), 12);
+    net.add_edge(n(3), n(6), 2);
+    assert_eq!(4, colour_count(&net));
+    for k in 2..5 {
+        let freq = enumerate_subgraphs(k, &net).unwrap();
+        assert_eq!(all_motifs(k, &net).unwrap(), freq);
+        assert!(freq.keys().any(|id| (0..k * k).any(|pos| id.digit(pos, 4) & 12 != 0)));
+    }
+
+    // The same shape in different colours is a different motif.
+    let triangle = |w| {
+        let mut net = Network::new();
+        let (a, b, c) = (net.add_node(String::new()), net.add_node(String::new()), net.add_node(String::new()));
+        net.add_edge(a, b, 1);
+        net.add_edge(b, c, w);
+        net.add_edge(a, c, 1);
+        motif_id(&canonicalize(net).unwrap(), 4).unwrap()
+    };
+    assert!(triangle(1) != triangle(4));
+    assert!(triangle(4) != triangle(8));
+    assert_eq!(triangle(4), triangle(4));
+}
+
+#[test]
+fn test_node_colours() {
+    // A TF -> TF -> gene feedforward loop and a TF -> gene -> gene one.
+    let (tf, gene) = (1, 2);
+    let mut net = Network::new();
+    let nodes = Vec::from_iter((0..6).map(|i| net.add_node(format!("{}", i))));
+    for &(a, b, c) in &[(0, 1, 2), (3, 4, 5)] {
+        net.add_edge(nodes[a], nodes[b], 1);
+        net.add_edge(nodes[b], nodes[c], 1);
+        net.add_edge(nodes[a], nodes[c], 1);
+    }
+    let colours = [tf, tf, gene, tf, gene, gene];
+    let plain = enumerate_subgraphs(3, &net).unwrap();
+    assert_eq!(vec![2], Vec::from_iter(plain.values().cloned()));
+    let coloured = enumerate_coloured_subgraphs(3, &net, &colours).unwrap();
+    assert_eq!(vec![1, 1], Vec::from_iter(coloured.values().cloned()));
+    for id in coloured.keys() {
+        let motif = canonicalize(id_to_network::<Directed>(3, 2, id)).unwrap();
+        assert_eq!(plain.keys().next(), Some(&motif_id(&motif, 2).unwrap()));
+        let mut node_colours = id_node_colours::<Directed>(3, 2, id);
+        node_colours.sort();
+        assert!(node_colours == [tf, tf, gene] || node_colours == [tf, gene, gene]);
+    }
+    // Colour 0 everywhere is the same as no colours.
+    assert_eq!(plain, enumerate_coloured_subgraphs(3, &net, &[0; 6]).unwrap());
+
+    assert!(enumerate_coloured_subgraphs(3, &net, &colours[..5]).is_err());
+    // Colour 4 needs wider digits than these edges do.
+    let wide = enumerate_coloured_subgraphs(3, &net, &[tf, tf, 4, tf, 4, 4]).unwrap();
+    assert!(wide.keys().all(|id| id_node_colours::<Directed>(3, 4, id).contains(&4)));
+}
+
+#[test]
+fn test_node_colours_random() {
+    let mut rng = Rng::new(8);
+    for _ in 0..5 {
+        let n = 15;
+        let mut net = Network::new();
+        for i in 0..n {
+            net.add_node(format!("{}", i));
+        }
+        for _ in 0..2 * n {
+            let (u, v) = (NodeIndex::new(rng.below(n)), NodeIndex::new(rng.below(n)));
+            net.add_edge(u, v, 1 + rng.below(3) as EdgeType);
+        }
+        let colours = Vec::from_iter((0..n).map(|_| rng.below(3) as NodeColour));
+        for k in 2..5 {
+            let coloured = enumerate_coloured_subgraphs(k, &net, &colours).unwrap();
+            assert_eq!(coloured, enumerate_subgraphs_parallel(k, &net, &colours, 3).unwrap());
+
+            // Forgetting the colours gives the uncoloured counts.
+            let mut plain = MotifFreq::new();
+            for (id, count) in &coloured {
+                let motif = canonicalize(id_to_network::<Directed>(k, 2, id)).unwrap();
+                *plain.entry(motif_id(&motif, 2).unwrap()).or_insert(0) += *count;
+            }
+            assert_eq!(enumerate_subgraphs(k, &net).unwrap(), plain);
+
+            // Relabelling the nodes, colours and all, changes nothing.
+            let mut order = Vec::from_iter(0..n);
+            for i in (1..n).rev() {
+                order.swap(i, rng.below(i + 1));
+            }
+            let mut shuffled = Network::new();
+            for o in &order {
+                shuffled.add_node(format!("{}", o));
+            }
+            let mut position = vec![0; n];
+            for (i, o) in order.iter().enumerate() {
+                position[*o] = i;
+            }
+            for e in net.raw_edges() {
+                shuffled.add_edge(NodeIndex::new(position[e.source().index()]),
+                                  NodeIndex::new(position[e.target().index()]),
+                                  e.weight);
+            }
+            let shuffled_colours = Vec::from_iter(order.iter().map(|o| colours[*o]));
+            assert_eq!(coloured, enumerate_coloured_subgraphs(k, &shuffled, &shuffled_colours).unwrap());
+            let both = enumerate_ensemble(k, &[net.clone(), shuffled], &[colours.clone(), shuffled_colours], 2);
+            assert_eq!(vec![coloured.clone(), coloured], both.unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_undirected() {
+    // A path a - b - c has digits for a - b, a - c and b - c only.
+    let mut path = Network::new_undirected();
+    let (a, b, c) = (path.add_node(String::new()), path.add_node(String::new()), path.add_node(String::new()));
+    path.add_edge(a, b, 1);
+    path.add_edge(c, b, 2);
+    assert_eq!(3, adjacency_digits(3, false));
+    assert_eq!(MotifId::from(1 | 2 << 4), motif_id(&path, 2).unwrap());
+    let again: Network<Undirected> = id_to_network(3, 2, &motif_id(&path, 2).unwrap());
+    assert_eq!(2, again[again.find_edge(c, b).unwrap()]);
+
+    // Every position of a 5-node triangle is used once.
+    let mut positions = Vec::from_iter((0..5).flat_map(|i| (i + 1..5).map(move |j| (i, j)))
+        .map(|(i, j)| digit_position(5, i, j, false).unwrap()));
+    assert_eq!(Some(digit_position(5, 4, 2, false).unwrap()), digit_position(5, 2, 4, false));
+    positions.sort();
+    assert_eq!(Vec::from_iter(0..10), positions);
+
+    // The graphlets on 2 to 5 nodes: 1, 2, 6 and 21 of them.
+    let mut rng = Rng::new(4);
+    let mut net = Network::new_undirected();
+    let nodes = Vec::from_iter((0..30).map(|i| net.add_node(format!("{}", i))));
+    for i in 0..30 {
+        for j in i + 1..30 {
+            if rng.below(2) == 0 {
+                net.add_edge(nodes[i], nodes[j], 1);
+            }
+        }
+    }
+    for &(k, graphlets) in &[(2, 1), (3, 2), (4, 6), (5, 21)] {
+        assert_eq!(graphlets, enumerate_subgraphs(k, &net).unwrap().len());
+    }
+    // With the orbits of the 3-node graphlets: the ends and middle of a path, and a triangle.
+    assert_eq!(3, orbit_counts(3, &net).unwrap().orbits.len());
+    assert_eq!(enumerate_subgraphs(4, &net).unwrap(), sample_subgraphs(4, &net, &[1.0; 4], 0).unwrap().sampled);
+
+    // The same as ignoring direction and counting symmetric directed networks, up to the IDs.
+    for _ in 0..5 {
+        let mut directed = Network::new();
+        let n = 15;
+        for i in 0..n {
+            directed.add_node(format!("{}", i));
+        }
+        for _ in 0..2 * n {
+            let (u, v) = (NodeIndex::new(rng.below(n)), NodeIndex::new(rng.below(n)));
+            if u != v {
+                directed.add_edge(u, v, 1 + rng.below(3) as EdgeType);
+            }
+        }
+        let undirected: Network<Undirected> = with_directedness(&directed);
+        let mut symmetric = directed.clone();
+        for e in undirected.raw_edges() {
+            for &(u, v) in &[(e.source(), e.target()), (e.target(), e.source())] {
+                match symmetric.find_edge(u, v) {
+                    Some(f) => symmetric[f] = e.weight,
+                    None => {
+                        symmetric.add_edge(u, v, e.weight);
+                    }
+                }
+            }
+        }
+        for k in 2..5 {
+            let counts = enumerate_subgraphs(k, &undirected).unwrap();
+            assert_eq!(all_motifs(k, &undirected).unwrap(), counts);
+            assert_eq!(enumerate_subgraphs_parallel(k, &undirected, &[], 3).unwrap(), counts);
+            let mut via_directed = Vec::from_iter(enumerate_subgraphs(k, &symmetric).unwrap().values().cloned());
+            let mut counts = Vec::from_iter(counts.values().cloned());
+            via_directed.sort();
+            counts.sort();
+            assert_eq!(via_directed, counts);
+        }
+    }
 }
 
 #[test]
 fn test_motif_id() {
     {
         let mut net = Network::new();
-        assert_eq!(0, motif_id(&net));
+        assert!(motif_id(&net, 2).unwrap().is_zero());
         net.add_node(String::new());
-        assert_eq!(0, motif_id(&net));
+        assert!(motif_id(&net, 2).unwrap().is_zero());
         net.add_node(String::new());
-        assert_eq!(0, motif_id(&net));
+        assert!(motif_id(&net, 2).unwrap().is_zero());
         net.add_node(String::new());
-        assert_eq!(0, motif_id(&net));
+        assert!(motif_id(&net, 2).unwrap().is_zero());
     }
 
     let net = network_from_paper();
     println!("{:?}", canonical_subnet(&net, &[1, 2, 3, 4, 5, 6]));
-    assert_eq!(0, motif_id(&canonical_subnet(&net, &[1])));
-    assert_eq!(motif_str("0100"), motif_id(&canonical_subnet(&net, &[1, 2])));
-    assert_eq!(motif_str("001000000"), motif_id(&canonical_subnet(&net, &[1, 2, 3])));
-    assert_eq!(motif_str("0001000000000000"), motif_id(&canonical_subnet(&net, &[1, 2, 3, 4])));
+    assert!(motif_id(&canonical_subnet(&net, &[1]), 2).unwrap().is_zero());
+    assert_eq!(motif_str("0100"), motif_id(&canonical_subnet(&net, &[1, 2]), 2).unwrap());
+    assert_eq!(motif_str("001000000"), motif_id(&canonical_subnet(&net, &[1, 2, 3]), 2).unwrap());
+    assert_eq!(motif_str("0001000000000000"), motif_id(&canonical_subnet(&net, &[1, 2, 3, 4]), 2).unwrap());
     assert_eq!(motif_str("0000100000000000000000000"),
-               motif_id(&canonical_subnet(&net, &[1, 2, 3, 4, 5])));
+               motif_id(&canonical_subnet(&net, &[1, 2, 3, 4, 5]), 2).unwrap());
     assert_eq!(motif_str("000010000001000000000000000000000000"),
-               motif_id(&canonical_subnet(&net, &[1, 2, 3, 4, 5, 6])));
+               motif_id(&canonical_subnet(&net, &[1, 2, 3, 4, 5, 6]), 2).unwrap());
 }
 
 #[test]
 fn test_motif_id_roundtrip() {
-    for id in 0..128 {
-        assert_eq!(motif_id(&id_to_network(5, id)), id);
+    for id in (0..128).map(MotifId::from) {
+        assert_eq!(motif_id(&id_to_network::<Directed>(5, 2, &id), 2).unwrap(), id);
     }
 }
 
+#[test]
+fn test_motif_id_large() {
+    // An 8 node clique of dual edges needs 4^64 = 2^128, well past a u64.
+    let n = 8;
+    let mut net = Network::new();
+    let nodes = Vec::from_iter((0..n).map(|i| net.add_node(format!("{}", i))));
+    for u in &nodes {
+        for v in &nodes {
+            if u != v {
+                net.add_edge(*u, *v, 3);
+            }
+        }
+    }
+    let id = motif_id(&net, 2).unwrap();
+    assert_eq!(motif_id(&id_to_network::<Directed>(n, 2, &id), 2).unwrap(), id);
+    assert_eq!(Ok(id.clone()), id.to_string().parse());
+
+    // Only differ in the last edge, which used to wrap to zero.
+    let mut a = Network::new();
+    let nodes = Vec::from_iter((0..6).map(|i| a.add_node(format!("{}", i))));
+    a.add_edge(nodes[0], nodes[1], 1);
+    let mut b = a.clone();
+    b.add_edge(nodes[5], nodes[4], 1);
+    assert!(motif_id(&a, 2).unwrap() < motif_id(&b, 2).unwrap());
+    assert_eq!(MotifId::from(4), motif_id(&a, 2).unwrap());
+    assert_eq!(motif_id(&id_to_network::<Directed>(6, 2, &motif_id(&b, 2).unwrap()), 2).unwrap(), motif_id(&b, 2).unwrap());
+}
+
+#[test]
+fn test_motif_id_display() {
+    assert_eq!("0", MotifId::new().to_string());
+    assert_eq!("12345", MotifId::from(12345).to_string());
+    let big = "340282366920938463463374607431768211456"; // 2^128
+    assert_eq!(big, big.parse::<MotifId>().unwrap().to_string());
+    assert_eq!(Err(ParseMotifIdError), "".parse::<MotifId>());
+    assert_eq!(Err(ParseMotifIdError), "12a".parse::<MotifId>());
+}
+
 #[cfg(test)]
 fn canonical_subnet(net: &Network, ns: &[usize]) -> Network {
-    canonicalize(net.subnet(&Vec::from_iter(ns.iter().map(|n| NodeIndex::new(*n - 1)))))
+    canonicalize(net.subnet(&Vec::from_iter(ns.iter().map(|n| NodeIndex::new(*n - 1)))).unwrap()).unwrap()
 }
 
 #[cfg(test)]
 fn motif_str(s: &str) -> MotifId {
-    MotifId::from_str_radix(s, MOTIF_BASE as u32).unwrap()
+    MotifId::from_str_radix(s, 4).unwrap()
 }
@@ -1,16 +1,19 @@
 use nauty_bindings;
 use nauty_bindings::{graph, optionblk};
 
+use error::*;
 use network::*;
+
+use std::cell::RefCell;
+use std::cmp;
 use std::iter::FromIterator;
-use std::sync::{StaticMutex, MUTEX_INIT};
+use std::slice;
 
-pub const MAXN: usize = WORDSIZE;
+/// The most vertices nauty can take, counting every layer: `NAUTY_INFINITY - 2`.
+pub const MAXN: usize = 0x40000000;
 pub const WORDSIZE: usize = 64;
 
-static NAUTY_LOCK: StaticMutex = MUTEX_INIT;
-
-fn default_options_graph() -> optionblk {
+pub fn default_options_graph() -> optionblk {
     optionblk {
         tc_level: 100,
         maxinvarlevel: 1,
@@ -29,48 +32,136 @@ pub fn default_options_digraph() -> optionblk {
     }
 }
 
-fn add_one_arc(g: &mut [graph], v: usize, w: usize) {
-    g[v] |= bit(w);
+/// Adds `v -> w` to the dense graph `g` with `m` setwords per row.
+fn add_one_arc(g: &mut [graph], m: usize, v: usize, w: usize) {
+    g[v * m + w / WORDSIZE] |= bit(w % WORDSIZE);
 }
 
-// fn add_one_edge(g: &mut [graph], v: usize, w: usize) {
-//     add_one_arc(g,v,w);
-//     add_one_arc(g,w,v);
-// }
+fn add_one_edge(g: &mut [graph], m: usize, v: usize, w: usize) {
+    add_one_arc(g, m, v, w);
+    add_one_arc(g, m, w, v);
+}
 
 fn bit(n: usize) -> nauty_bindings::setword {
-    debug_assert!(n <= WORDSIZE, "n > WORDSIZE: {}", n);
+    debug_assert!(n < WORDSIZE, "n >= WORDSIZE: {}", n);
     1 << (WORDSIZE - 1 - n)
 }
 
-pub fn canonical_labelling(net: &Network) -> Vec<NodeIndex> {
-    let mut g = [0; MAXN];
-    let mut cg = [0; MAXN];
-    let mut lab = [0; MAXN];
-    let mut ptn = [0; MAXN];
-    let mut orbits = [0; MAXN];
-    let mut options = default_options_digraph();
+/// The canonical order of `net`'s nodes.
+pub fn canonical_labelling<D: Directedness>(net: &Network<D>) -> Result<Vec<NodeIndex>> {
+    Ok(try!(automorphisms(net)).labelling)
+}
+
+/// The canonical order of `net`'s nodes, and the automorphism orbit of each node, named by its
+/// smallest node.
+pub fn canonical_orbits<D: Directedness>(net: &Network<D>) -> Result<(Vec<NodeIndex>, Vec<NodeIndex>)> {
+    let a = try!(automorphisms(net));
+    Ok((a.labelling, a.orbits))
+}
+
+/// The symmetries of a network, respecting edge direction, `EdgeType` and any node colours.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Automorphisms {
+    /// The nodes in canonical order.
+    pub labelling: Vec<NodeIndex>,
+    /// The orbit of each node, named by its smallest node. Nodes in the same orbit play the same
+    /// role in the network.
+    pub orbits: Vec<NodeIndex>,
+    /// The number of automorphisms. May be rounded for very large groups.
+    pub group_size: f64,
+    /// Automorphisms generating the whole group, each mapping node `v` to `generator[v]`.
+    pub generators: Vec<Vec<NodeIndex>>,
+}
+
+thread_local! {
+    static GENERATORS: RefCell<Vec<Vec<::libc::c_int>>> = RefCell::new(Vec::new());
+}
+
+unsafe extern "C" fn collect_generator(_count: ::libc::c_int,
+                                       perm: *mut ::libc::c_int,
+                                       _orbits: *mut ::libc::c_int,
+                                       _numorbits: ::libc::c_int,
+                                       _stabvertex: ::libc::c_int,
+                                       n: ::libc::c_int) {
+    let perm = slice::from_raw_parts(perm, n as usize).to_vec();
+    GENERATORS.with(|g| g.borrow_mut().push(perm));
+}
+
+/// Runs nauty on `net`, with every node the same colour.
+pub fn automorphisms<D: Directedness>(net: &Network<D>) -> Result<Automorphisms> {
+    automorphisms_coloured(net, &[])
+}
+
+/// Runs nauty on `net`, whose node `v` has colour `colours[v]`. Nodes are only swapped with nodes
+/// of the same colour, and the canonical order lists them by colour, smallest first. No colours
+/// at all means every node is colour 0.
+///
+/// Each bit of the `EdgeType`s gets its own copy of the nodes, a layer holding the edges with
+/// that bit set. Copies of the same node are joined by an edge to the next layer, and the layers
+/// are kept apart by the initial partition, so only relabellings that agree on every layer count.
+///
+/// The graph given to nauty is dense, with `ceil(n * layers / WORDSIZE)` setwords per row, so
+/// memory grows with the square of the number of nodes. Fails with `TooManyNodes` if the layers
+/// need more than `MAXN` nodes in all.
+///
+/// Undirected networks are given to nauty as undirected graphs.
+pub fn automorphisms_coloured<D: Directedness>(net: &Network<D>, colours: &[NodeColour]) -> Result<Automorphisms> {
+    let mut options = if D::is_directed() { default_options_digraph() } else { default_options_graph() };
     let mut stats = nauty_bindings::statsblk::default();
 
     options.getcanon = 1;
+    options.defaultptn = 0;
+    options.userautomproc = Some(collect_generator);
 
     let n = net.node_count();
-    assert!(n * 2 <= MAXN, "number of nodes greater than MAXN ({}): {}", MAXN, n);
+    if !colours.is_empty() && colours.len() != n {
+        return Err(Error::NodeColourCount { nodes: n, colours: colours.len() });
+    }
+    let layers = cmp::max(1, colour_count(net));
+    if n * layers > MAXN {
+        return Err(Error::TooManyNodes { nodes: n, max: MAXN / layers });
+    }
+    let total = n * layers;
+    let m = cmp::max(1, (total + WORDSIZE - 1) / WORDSIZE);
+    // Never empty, so nauty always gets real pointers.
+    let mut g = vec![0; cmp::max(1, total) * m];
+    let mut cg = vec![0; cmp::max(1, total) * m];
+    let mut lab = vec![0; cmp::max(1, total)];
+    let mut ptn = vec![0; cmp::max(1, total)];
+    let mut orbits = vec![0; cmp::max(1, total)];
 
     for e in net.raw_edges() {
         let mut level = 0;
         let mut weight = e.weight;
         while weight != 0 {
             if weight & 1 == 1 {
-                add_one_arc(&mut g, e.source().index() + level, e.target().index() + level);
+                let (v, w) = (e.source().index() + level, e.target().index() + level);
+                if D::is_directed() {
+                    add_one_arc(&mut g, m, v, w);
+                } else {
+                    add_one_edge(&mut g, m, v, w);
+                }
             }
             level += n;
             weight >>= 1;
         }
     }
+    // Each layer is split into a cell per node colour.
+    let colour = |v: usize| colours.get(v).cloned().unwrap_or(0);
+    let mut order = Vec::from_iter(0..n);
+    order.sort_by_key(|v| colour(*v));
+    for v in 0..total {
+        let (level, i) = (v - v % n, v % n);
+        lab[v] = (level + order[i]) as ::libc::c_int;
+        ptn[v] = if i == n - 1 || colour(order[i]) != colour(order[i + 1]) { 0 } else { 1 };
+        if v + n < total {
+            add_one_edge(&mut g, m, v, v + n);
+        }
+    }
 
+    // nauty is built with thread-local working storage, so no lock is needed.
+    GENERATORS.with(|g| g.borrow_mut().clear());
     unsafe {
-        let _g = NAUTY_LOCK.lock().unwrap();
         nauty_bindings::densenauty(
             g.as_mut_ptr(),
             lab.as_mut_ptr(),
@@ -78,18 +169,34 @@ pub fn canonical_labelling(net: &Network) -> Vec<NodeIndex> {
             orbits.as_mut_ptr(),
             &mut options,
             &mut stats,
-            1, // m
-            n as ::libc::c_int,
+            m as ::libc::c_int,
+            total as ::libc::c_int,
             cg.as_mut_ptr());
     }
-    Vec::from_iter(lab[..n].iter().map(|idx| NodeIndex::new(*idx as usize)))
+    let nodes = |idxs: &[::libc::c_int]| Vec::from_iter(idxs.iter().map(|idx| NodeIndex::new(*idx as usize)));
+    // The first cell is the first layer, so it lists the nodes in canonical order. Automorphisms
+    // keep the layers in place, so the first layer says all there is about them.
+    Ok(Automorphisms {
+        labelling: nodes(&lab[..n]),
+        orbits: nodes(&orbits[..n]),
+        group_size: stats.grpsize1 * 10f64.powi(stats.grpsize2),
+        generators: GENERATORS.with(|g| g.borrow_mut().drain(..).map(|p| nodes(&p[..n])).collect()),
+    })
 }
 
-pub fn canonicalize(net: Network) -> Network {
-    let lab = canonical_labelling(&net);
+pub fn canonicalize<D: Directedness>(net: Network<D>) -> Result<Network<D>> {
+    let lab = try!(canonical_labelling(&net));
     net.subnet(&lab)
 }
 
+/// `canonicalize` with node colours, giving the colours in canonical order too.
+pub fn canonicalize_coloured<D: Directedness>(net: Network<D>, colours: &[NodeColour])
+                                              -> Result<(Network<D>, Vec<NodeColour>)> {
+    let lab = try!(automorphisms_coloured(&net, colours)).labelling;
+    let colours = lab.iter().map(|v| colours.get(v.index()).cloned().unwrap_or(0)).collect();
+    Ok((try!(net.subnet(&lab)), colours))
+}
+
 #[test]
 fn test_canon() {
     let mut net1 = Network::new();
@@ -124,10 +231,212 @@ fn test_canon() {
     net1.add_edge(e1, a1, 2);
     net2.add_edge(e2, a2, 2);
 
-    let lab1 = canonical_labelling(&net1);
-    let lab2 = canonical_labelling(&net2);
+    let lab1 = canonical_labelling(&net1).unwrap();
+    let lab2 = canonical_labelling(&net2).unwrap();
 
     for (l1, l2) in lab1.into_iter().zip(lab2.into_iter()) {
         assert_eq!(net1[l1], net2[l2]);
     }
 }
+
+#[test]
+fn test_canon_threads() {
+    use randomize::Rng;
+    use std::thread;
+
+    let mut rng = Rng::new(1);
+    let nets = Vec::from_iter((0..200).map(|_| {
+        let n = 3 + rng.below(12);
+        let mut net = Network::new();
+        for i in 0..n {
+            net.add_node(format!("{}", i));
+        }
+        for _ in 0..2 * n {
+            let (u, v) = (rng.below(n), rng.below(n));
+            if u != v && net.find_edge(NodeIndex::new(u), NodeIndex::new(v)).is_none() {
+                net.add_edge(NodeIndex::new(u), NodeIndex::new(v), 1 + rng.below(3) as EdgeType);
+            }
+        }
+        net
+    }));
+    let expected = Vec::from_iter(nets.iter().map(|net| canonical_labelling(net).unwrap()));
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                for _ in 0..20 {
+                    for (net, lab) in nets.iter().zip(&expected) {
+                        assert_eq!(*lab, canonical_labelling(net).unwrap());
+                    }
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn test_canon_layers() {
+    use motifs::motif_id;
+
+    // A cascade of activation then repression, and its mirror image.
+    let cascade = |first: EdgeType, second: EdgeType, order: &[usize]| {
+        let mut net = Network::new();
+        let nodes = Vec::from_iter((0..3).map(|i| net.add_node(format!("{}", i))));
+        net.add_edge(nodes[order[0]], nodes[order[1]], first);
+        net.add_edge(nodes[order[1]], nodes[order[2]], second);
+        motif_id(&canonicalize(net).unwrap(), 2).unwrap()
+    };
+    let orders = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+    for order in &orders {
+        assert_eq!(cascade(1, 2, &orders[0]), cascade(1, 2, order));
+        assert_eq!(cascade(2, 1, &orders[0]), cascade(2, 1, order));
+        // Only repression, so nothing at all in the first layer.
+        assert_eq!(cascade(2, 2, &orders[0]), cascade(2, 2, order));
+        assert_eq!(cascade(3, 2, &orders[0]), cascade(3, 2, order));
+        assert!(cascade(1, 2, &orders[0]) != cascade(2, 1, order));
+        assert!(cascade(1, 3, &orders[0]) != cascade(1, 1, order));
+    }
+}
+
+#[test]
+fn test_orbits() {
+    // A feedforward loop has no symmetries, a fan out of three has its three targets.
+    let mut ffl = Network::new();
+    let a = ffl.add_node("a".to_string());
+    let b = ffl.add_node("b".to_string());
+    let c = ffl.add_node("c".to_string());
+    ffl.add_edge(a, b, 1);
+    ffl.add_edge(a, c, 1);
+    ffl.add_edge(b, c, 1);
+    assert_eq!(vec![a, b, c], canonical_orbits(&ffl).unwrap().1);
+
+    let mut fan = Network::new();
+    let targets = Vec::from_iter((0..3).map(|i| fan.add_node(format!("{}", i))));
+    let source = fan.add_node("source".to_string());
+    for t in &targets {
+        fan.add_edge(source, *t, 1);
+    }
+    assert_eq!(vec![targets[0], targets[0], targets[0], source], canonical_orbits(&fan).unwrap().1);
+    // Unless the edges differ.
+    fan.add_edge(source, targets[2], 2);
+    assert_eq!(vec![targets[0], targets[0], targets[2], source], canonical_orbits(&fan).unwrap().1);
+}
+
+#[test]
+fn test_automorphisms() {
+    let network = |n: usize, edges: &[(usize, usize, EdgeType)]| {
+        let mut net = Network::new();
+        for i in 0..n {
+            net.add_node(format!("{}", i));
+        }
+        for &(u, v, w) in edges {
+            net.add_edge(NodeIndex::new(u), NodeIndex::new(v), w);
+        }
+        net
+    };
+    // All the permutations the generators give, by closing under composition.
+    let group = |a: &Automorphisms| {
+        let n = a.labelling.len();
+        let mut group = vec![Vec::from_iter((0..n).map(NodeIndex::new))];
+        let mut i = 0;
+        while i < group.len() {
+            for g in &a.generators {
+                let p = Vec::from_iter(group[i].iter().map(|v| g[v.index()]));
+                if !group.contains(&p) {
+                    group.push(p);
+                }
+            }
+            i += 1;
+        }
+        group
+    };
+
+    let ffl = automorphisms(&network(3, &[(0, 1, 1), (0, 2, 1), (1, 2, 1)])).unwrap();
+    assert_eq!(1.0, ffl.group_size);
+    assert!(ffl.generators.is_empty());
+
+    let fan = network(4, &[(0, 1, 1), (0, 2, 1), (0, 3, 1)]);
+    let a = automorphisms(&fan).unwrap();
+    assert_eq!(6.0, a.group_size);
+    assert_eq!(6, group(&a).len());
+    for p in group(&a) {
+        assert_eq!(NodeIndex::new(0), p[0]);
+    }
+
+    let cycle = automorphisms(&network(3, &[(0, 1, 1), (1, 2, 1), (2, 0, 1)])).unwrap();
+    assert_eq!(3.0, cycle.group_size);
+    assert_eq!(3, group(&cycle).len());
+
+    // A different type on one edge breaks the symmetry.
+    let cycle = automorphisms(&network(3, &[(0, 1, 1), (1, 2, 1), (2, 0, 2)])).unwrap();
+    assert_eq!(1.0, cycle.group_size);
+    assert_eq!(vec![NodeIndex::new(0), NodeIndex::new(1), NodeIndex::new(2)], cycle.orbits);
+
+    // So does colouring one node of the fan's targets, and the canonical order is by colour.
+    let a = automorphisms_coloured(&fan, &[0, 0, 5, 0]).unwrap();
+    assert_eq!(2.0, a.group_size);
+    assert_eq!(NodeIndex::new(2), a.labelling[3]);
+    assert_eq!(vec![NodeIndex::new(0), NodeIndex::new(1), NodeIndex::new(2), NodeIndex::new(1)], a.orbits);
+    assert_eq!(a, automorphisms_coloured(&fan, &[1, 1, 6, 1]).unwrap());
+    assert_eq!(automorphisms(&fan).unwrap(), automorphisms_coloured(&fan, &[0; 4]).unwrap());
+    match automorphisms_coloured(&fan, &[0, 1]) {
+        Err(Error::NodeColourCount { nodes: 4, colours: 2 }) => {}
+        r => panic!("expected NodeColourCount, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_automorphisms_undirected() {
+    let mut path = Network::new_undirected();
+    let nodes = Vec::from_iter((0..3).map(|i| path.add_node(format!("{}", i))));
+    path.add_edge(nodes[1], nodes[0], 1);
+    path.add_edge(nodes[1], nodes[2], 1);
+    let a = automorphisms(&path).unwrap();
+    assert_eq!(2.0, a.group_size);
+    assert_eq!(vec![nodes[0], nodes[1], nodes[0]], a.orbits);
+
+    path.add_edge(nodes[0], nodes[2], 1);
+    assert_eq!(6.0, automorphisms(&path).unwrap().group_size);
+    // Unless an edge has another colour.
+    let e = path.find_edge(nodes[2], nodes[0]).unwrap();
+    path[e] = 2;
+    assert_eq!(2.0, automorphisms(&path).unwrap().group_size);
+    assert_eq!(canonicalize(path.clone()).unwrap().edge_count(), 3);
+}
+
+#[test]
+fn test_canon_large() {
+    use motifs::motif_id;
+    use randomize::Rng;
+
+    // A cycle of 150 nodes in two layers takes five setwords per row. Alternating activation and
+    // repression halves its rotations, and a dual chord breaks all but one of those.
+    let n = 150;
+    let mut net = Network::new();
+    let nodes = Vec::from_iter((0..n).map(|i| net.add_node(format!("{}", i))));
+    for i in 0..n {
+        net.add_edge(nodes[i], nodes[(i + 1) % n], 1 + (i % 2) as EdgeType);
+    }
+    let a = automorphisms(&net).unwrap();
+    assert_eq!(75.0, a.group_size);
+    net.add_edge(nodes[0], nodes[n / 2], 3);
+    let a = automorphisms(&net).unwrap();
+    assert_eq!(1.0, a.group_size);
+
+    // Shuffling the nodes gives the same canonical form.
+    let mut rng = Rng::new(11);
+    let mut order = Vec::from_iter(0..n);
+    for i in (1..n).rev() {
+        order.swap(i, rng.below(i + 1));
+    }
+    let mut shuffled = Network::new();
+    let new_nodes = Vec::from_iter((0..n).map(|i| shuffled.add_node(format!("{}", order[i]))));
+    let mut position = vec![0; n];
+    for (i, o) in order.iter().enumerate() {
+        position[*o] = i;
+    }
+    for e in net.raw_edges() {
+        shuffled.add_edge(new_nodes[position[e.source().index()]], new_nodes[position[e.target().index()]], e.weight);
+    }
+    assert_eq!(motif_id(&canonicalize(net).unwrap(), 2).unwrap(),
+               motif_id(&canonicalize(shuffled).unwrap(), 2).unwrap());
+}
@@ -1,32 +1,96 @@
+use error::*;
+
 use petgraph;
+use petgraph::visit::EdgeRef;
 use std::collections::HashMap;
-
-pub type EdgeType = u8;
+use std::mem;
+
+/// The colours of an edge, one bit each: 1 is an activation and 2 a repression, so 3 is a dual
+/// edge. Other kinds of interaction take the higher bits.
+pub type EdgeType = u32;
+/// A category of node, such as transcription factor, target gene or miRNA. 0 is the colour of
+/// nodes with no category.
+pub type NodeColour = u32;
 pub type EdgeIndex = petgraph::graph::EdgeIndex;
 pub type NodeIndex = petgraph::graph::NodeIndex;
 
-pub type Network = petgraph::Graph<String, EdgeType>;
+pub use petgraph::{Directed, Undirected};
+/// `Directed` or `Undirected`: whether a `Network`'s edges have a direction.
+pub use petgraph::EdgeType as Directedness;
 
-pub trait SubNetwork {
-    fn subnet<N: AsRef<[NodeIndex]>>(&self, ns: N) -> Network;
+/// A network of named nodes. Undirected networks hold each edge once, either way round.
+pub type Network<D = Directed> = petgraph::Graph<String, EdgeType, D>;
+
+/// The number of edge colours `net` uses, counting up to its highest colour bit.
+pub fn colour_count<D: Directedness>(net: &Network<D>) -> usize {
+    let all = net.raw_edges().iter().fold(0, |w, e| w | e.weight);
+    mem::size_of::<EdgeType>() * 8 - all.leading_zeros() as usize
+}
+
+/// A copy of `net` as a `Network<D>`. Edges between the same nodes are merged into one with the
+/// colours of all of them, so as an `Undirected` network `u -> v` and `v -> u` become one edge.
+pub fn with_directedness<D: Directedness>(net: &Network) -> Network<D> {
+    let mut copy = Network::with_capacity(net.node_count(), net.edge_count());
+    for n in net.node_indices() {
+        copy.add_node(net[n].clone());
+    }
+    for e in net.raw_edges() {
+        match copy.find_edge(e.source(), e.target()) {
+            Some(f) => copy[f] |= e.weight,
+            None => {
+                copy.add_edge(e.source(), e.target(), e.weight);
+            }
+        }
+    }
+    copy
 }
 
-impl SubNetwork for Network {
-    fn subnet<N: AsRef<[NodeIndex]>>(&self, ns: N) -> Network {
+/// A copy of `net` with edges between the same nodes merged into one with the colours of all of
+/// them, as the enumerations count them.
+pub fn merge_parallel_edges<D: Directedness>(net: &Network<D>) -> Network<D> {
+    let mut copy = Network::with_capacity(net.node_count(), net.edge_count());
+    for n in net.node_indices() {
+        copy.add_node(net[n].clone());
+    }
+    for e in net.raw_edges() {
+        match copy.find_edge(e.source(), e.target()) {
+            Some(f) => copy[f] |= e.weight,
+            None => {
+                copy.add_edge(e.source(), e.target(), e.weight);
+            }
+        }
+    }
+    copy
+}
+
+pub trait SubNetwork: Sized {
+    /// The subgraph induced by `ns`, with node `i` of it being `ns[i]`.
+    fn subnet<N: AsRef<[NodeIndex]>>(&self, ns: N) -> Result<Self>;
+}
+
+impl<D: Directedness> SubNetwork for Network<D> {
+    fn subnet<N: AsRef<[NodeIndex]>>(&self, ns: N) -> Result<Network<D>> {
         let ns = ns.as_ref();
         let mut subnet = Network::with_capacity(ns.len(), ns.len() * 2);
-        let old_to_new: HashMap<NodeIndex, NodeIndex> =
-            ns.iter().map(|n| (*n, subnet.add_node(self[*n].clone()))).collect();
+        let mut old_to_new = HashMap::new();
+        for n in ns {
+            match self.node_weight(*n) {
+                Some(name) => old_to_new.insert(*n, subnet.add_node(name.clone())),
+                None => return Err(Error::NoSuchNode(*n)),
+            };
+        }
         for u in ns {
-            for (v, e) in self.edges(*u) {
-                match (old_to_new.get(u), old_to_new.get(&v)) {
-                    (Some(u), Some(v)) => {subnet.add_edge(*u, *v, *e);},
-                    (Some(_), None) => {},
-                    _ => {panic!("Huh!??!??!");},
+            for e in self.edges(*u) {
+                let (v, w) = (e.target(), *e.weight());
+                // Undirected edges are seen from both ends, so only take them from one.
+                if let Some(v) = old_to_new.get(&v) {
+                    if D::is_directed() || old_to_new[u] <= *v {
+                        subnet.add_edge(old_to_new[u], *v, w);
+                    }
                 }
             }
         }
-        subnet
+        Ok(subnet)
     }
 }
 
@@ -48,25 +112,53 @@ fn test_network() {
             }
         }
     }
-    let sub = net.subnet(&[]);
+    let sub = net.subnet(&[]).unwrap();
     assert_eq!(0, sub.node_count());
     assert_eq!(0, sub.edge_count());
 
-    let sub = net.subnet(&[b]);
+    let sub = net.subnet(&[b]).unwrap();
     assert_eq!(1, sub.node_count());
     assert_eq!(1, sub.edge_count());
 
-    let sub = net.subnet(&[a, c, e]);
+    let sub = net.subnet(&[a, c, e]).unwrap();
     assert_eq!(3, sub.node_count());
     assert_eq!(9, sub.edge_count());
 
-    let sub = net.subnet(&[a, b, c, e]);
+    let sub = net.subnet(&[a, b, c, e]).unwrap();
     assert_eq!(4, sub.node_count());
     assert_eq!(16, sub.edge_count());
 
-    let sub = net.subnet(&[e, d, c, b, a]);
+    let sub = net.subnet(&[e, d, c, b, a]).unwrap();
     assert_eq!(5, sub.node_count());
     assert_eq!(25, sub.edge_count());
+
+    match net.subnet(&[a, NodeIndex::new(5)]) {
+        Err(Error::NoSuchNode(n)) => assert_eq!(5, n.index()),
+        r => panic!("expected NoSuchNode, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_with_directedness() {
+    let mut net = Network::new();
+    let a = net.add_node("a".to_string());
+    let b = net.add_node("b".to_string());
+    let c = net.add_node("c".to_string());
+    net.add_edge(a, b, 1);
+    net.add_edge(b, a, 2);
+    net.add_edge(b, c, 1);
+
+    let und: Network<Undirected> = with_directedness(&net);
+    assert_eq!(3, und.node_count());
+    assert_eq!(2, und.edge_count());
+    assert_eq!(3, und[und.find_edge(b, a).unwrap()]);
+    assert_eq!(1, und[und.find_edge(c, b).unwrap()]);
+    assert_eq!(3, with_directedness::<Directed>(&net).edge_count());
+
+    // Each undirected edge once, whichever end comes first.
+    let sub = und.subnet(&[c, b, a]).unwrap();
+    assert_eq!(2, sub.edge_count());
+    assert_eq!(3, sub[sub.find_edge(NodeIndex::new(1), NodeIndex::new(2)).unwrap()]);
 }
 
 // The real network in figure 2 from R. Milo, et. al 2002; Network Motifs: Simple Building
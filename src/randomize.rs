@@ -0,0 +1,190 @@
+use network::*;
+
+use std::collections::{BTreeMap, HashSet};
+
+pub const DEFAULT_SWAPS_PER_EDGE: usize = 100;
+
+/// A small xorshift64* generator, so that ensembles are reproducible from a seed on every
+/// platform.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // splitmix64 spreads out small seeds and never leaves the state at zero.
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        Rng { state: if z == 0 { 0x9e3779b97f4a7c15 } else { z } }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    /// Uniform in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0, "empty range");
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// A null model of `net` by Markov-chain edge switching: `a -> b, c -> d` becomes
+/// `a -> d, c -> b` for two edges of the same `EdgeType`. This keeps every node's in- and
+/// out-degree for each `EdgeType`, and never creates self-loops or parallel edges.
+///
+/// Undirected edges are switched the same way, taking `c - d` either way round at random, which
+/// keeps every node's degree for each `EdgeType`.
+///
+/// `swaps_per_edge * net.edge_count()` switches are attempted.
+pub fn randomize<D: Directedness>(net: &Network<D>, swaps_per_edge: usize, rng: &mut Rng) -> Network<D> {
+    // Undirected edges are known by their nodes in order.
+    let arc = |u: NodeIndex, v: NodeIndex| if D::is_directed() || u < v { (u, v) } else { (v, u) };
+    let mut edges: Vec<_> = net.raw_edges().iter().map(|e| (e.source(), e.target(), e.weight)).collect();
+    let mut arcs: HashSet<_> = edges.iter().map(|&(u, v, _)| arc(u, v)).collect();
+    let mut by_type = BTreeMap::new();
+    for (i, &(_, _, w)) in edges.iter().enumerate() {
+        by_type.entry(w).or_insert_with(Vec::new).push(i);
+    }
+
+    for _ in 0..swaps_per_edge * edges.len() {
+        let i = rng.below(edges.len());
+        let same_type = &by_type[&edges[i].2];
+        let j = same_type[rng.below(same_type.len())];
+        let (a, b, _) = edges[i];
+        let (c, d) = match edges[j] {
+            (c, d, _) if D::is_directed() || rng.next_u64() & 1 == 0 => (c, d),
+            (d, c, _) => (c, d),
+        };
+        if a == c || b == d || a == d || c == b || arcs.contains(&arc(a, d)) || arcs.contains(&arc(c, b)) {
+            continue;
+        }
+        arcs.remove(&arc(a, b));
+        arcs.remove(&arc(c, d));
+        arcs.insert(arc(a, d));
+        arcs.insert(arc(c, b));
+        edges[i].1 = d;
+        edges[j].0 = c;
+        edges[j].1 = b;
+    }
+
+    let mut random = Network::with_capacity(net.node_count(), edges.len());
+    for n in net.node_indices() {
+        random.add_node(net[n].clone());
+    }
+    for (u, v, w) in edges {
+        random.add_edge(u, v, w);
+    }
+    random
+}
+
+/// `count` independent randomizations of `net`, generated from `seed`.
+pub fn ensemble<D: Directedness>(net: &Network<D>, count: usize, swaps_per_edge: usize, seed: u64) -> Vec<Network<D>> {
+    let mut rng = Rng::new(seed);
+    (0..count).map(|_| randomize(net, swaps_per_edge, &mut rng)).collect()
+}
+
+#[cfg(test)]
+fn degrees<D: Directedness>(net: &Network<D>) -> BTreeMap<(NodeIndex, EdgeType), (usize, usize)> {
+    let mut degrees = BTreeMap::new();
+    for e in net.raw_edges() {
+        degrees.entry((e.source(), e.weight)).or_insert((0, 0)).0 += 1;
+        degrees.entry((e.target(), e.weight)).or_insert((0, 0)).1 += 1;
+    }
+    degrees
+}
+
+#[test]
+fn test_randomize_preserves_degrees() {
+    let mut net = network_from_paper();
+    // Mix in some other edge types so there is something to keep apart.
+    let (a, b, c) = (NodeIndex::new(6), NodeIndex::new(8), NodeIndex::new(10));
+    net.add_edge(a, b, 2);
+    net.add_edge(b, c, 2);
+    net.add_edge(c, a, 3);
+
+    let mut rng = Rng::new(7);
+    for _ in 0..20 {
+        let random = randomize(&net, 10, &mut rng);
+        assert_eq!(net.node_count(), random.node_count());
+        assert_eq!(net.edge_count(), random.edge_count());
+        assert_eq!(degrees(&net), degrees(&random));
+        let mut arcs = HashSet::new();
+        for e in random.raw_edges() {
+            assert!(e.source() != e.target());
+            assert!(arcs.insert((e.source(), e.target())));
+        }
+    }
+}
+
+#[test]
+fn test_randomize_undirected() {
+    let mut net = with_directedness::<Undirected>(&network_from_paper());
+    let (a, b, c) = (NodeIndex::new(6), NodeIndex::new(8), NodeIndex::new(10));
+    net.add_edge(a, b, 2);
+    net.add_edge(c, b, 2);
+    // The degree of each node, whichever end of its edges it is at.
+    let total_degrees = |net: &Network<Undirected>| {
+        let mut total = BTreeMap::new();
+        for (&(n, w), &(out, into)) in &degrees(net) {
+            *total.entry((n, w)).or_insert(0) += out + into;
+        }
+        total
+    };
+
+    let mut rng = Rng::new(3);
+    let mut changed = false;
+    for _ in 0..20 {
+        let random = randomize(&net, 10, &mut rng);
+        assert_eq!(net.edge_count(), random.edge_count());
+        assert_eq!(total_degrees(&net), total_degrees(&random));
+        let mut edges = HashSet::new();
+        for e in random.raw_edges() {
+            assert!(e.source() != e.target());
+            let (u, v) = (e.source().min(e.target()), e.source().max(e.target()));
+            assert!(edges.insert((u, v)));
+        }
+        changed |= edges != net.raw_edges().iter()
+            .map(|e| (e.source().min(e.target()), e.source().max(e.target())))
+            .collect();
+    }
+    assert!(changed);
+}
+
+#[test]
+fn test_ensemble_is_seeded() {
+    let net = network_from_paper();
+    let edges = |nets: Vec<Network>| -> Vec<Vec<_>> {
+        nets.iter().map(|n| n.raw_edges().iter().map(|e| (e.source(), e.target())).collect()).collect()
+    };
+    let a = edges(ensemble(&net, 5, 10, 42));
+    assert_eq!(a, edges(ensemble(&net, 5, 10, 42)));
+    assert!(a != edges(ensemble(&net, 5, 10, 43)));
+    let original: Vec<_> = net.raw_edges().iter().map(|e| (e.source(), e.target())).collect();
+    assert!(a.iter().any(|r| *r != original));
+}
+
+#[test]
+fn test_rng_below() {
+    let mut rng = Rng::new(0);
+    let mut seen = [0; 5];
+    for _ in 0..1000 {
+        seen[rng.below(5)] += 1;
+    }
+    assert!(seen.iter().all(|&s| s > 150));
+    for _ in 0..1000 {
+        let f = rng.next_f64();
+        assert!(f >= 0.0 && f < 1.0);
+    }
+}
@@ -0,0 +1,181 @@
+use network::*;
+
+use std::f64::consts::PI;
+use std::io;
+use std::io::prelude::*;
+
+/// The width and height of a drawing, in pixels.
+pub const SVG_SIZE: f64 = 120.0;
+const RADIUS: f64 = 45.0;
+const NODE_RADIUS: f64 = 5.0;
+/// How far apart the two edges of a mutual pair are drawn.
+const MUTUAL_OFFSET: f64 = 3.0;
+
+/// Node positions around the unit circle, starting at the top and going clockwise, with `y` up.
+pub fn circular_layout(n: usize) -> Vec<(f64, f64)> {
+    (0..n).map(|i| {
+        let t = 2. * PI * i as f64 / n as f64;
+        (t.sin(), t.cos())
+    }).collect()
+}
+
+/// The arrowhead for edges of type `w`: the usual arrow for activation, a bar for repression, a
+/// diamond for dual edges and a circle for anything else. The names are Graphviz arrow shapes, and
+/// the ids of the SVG markers.
+pub fn arrowhead(w: EdgeType) -> &'static str {
+    match w {
+        1 => "normal",
+        2 => "tee",
+        3 => "diamond",
+        _ => "circle",
+    }
+}
+
+const MARKERS: &'static str = r#"<defs>
+<marker id="normal" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="7" markerHeight="7" orient="auto"><path d="M0,0 L10,5 L0,10 z"/></marker>
+<marker id="tee" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="7" markerHeight="7" orient="auto"><path d="M7,0 L10,0 L10,10 L7,10 z"/></marker>
+<marker id="diamond" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="7" markerHeight="7" orient="auto"><path d="M0,5 L5,0 L10,5 L5,10 z"/></marker>
+<marker id="circle" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="7" markerHeight="7" orient="auto"><circle cx="5" cy="5" r="5"/></marker>
+</defs>
+"#;
+
+/// Fills for node colours 1 and up, in turn. Colour 0 is black.
+const NODE_FILLS: [&'static str; 6] = ["#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b"];
+
+/// Draws `net` as a standalone SVG, with its nodes in `circular_layout` order and an arrowhead
+/// on each edge for its type. Undirected edges are plain lines, and self-loops are left out.
+pub fn write_svg<W: Write, D: Directedness>(writer: W, net: &Network<D>) -> io::Result<()> {
+    write_coloured_svg(writer, net, &[])
+}
+
+/// `write_svg`, filling node `v` by its colour `colours[v]`. No colours at all means every node
+/// is colour 0.
+pub fn write_coloured_svg<W: Write, D: Directedness>(writer: W,
+                                                     net: &Network<D>,
+                                                     colours: &[NodeColour])
+                                                     -> io::Result<()> {
+    write_drawing(writer, net, colours, D::is_directed())
+}
+
+/// The arrowhead markers on their own, in an SVG that takes no space. The markers have fixed
+/// ids, so a page with several drawings inline writes them once, before the first.
+pub fn write_svg_markers<W: Write>(mut writer: W) -> io::Result<()> {
+    try!(writeln!(writer,
+                  r#"<svg xmlns="http://www.w3.org/2000/svg" width="0" height="0" style="position: absolute">"#));
+    try!(writer.write_all(MARKERS.as_bytes()));
+    writeln!(writer, "</svg>")
+}
+
+/// `write_coloured_svg` without the arrowhead markers, for a page that has `write_svg_markers`.
+pub fn write_svg_body<W: Write, D: Directedness>(writer: W,
+                                                 net: &Network<D>,
+                                                 colours: &[NodeColour])
+                                                 -> io::Result<()> {
+    write_drawing(writer, net, colours, false)
+}
+
+fn write_drawing<W: Write, D: Directedness>(mut writer: W,
+                                            net: &Network<D>,
+                                            colours: &[NodeColour],
+                                            markers: bool)
+                                            -> io::Result<()> {
+    let c = SVG_SIZE / 2.;
+    let pos: Vec<_> = circular_layout(net.node_count()).into_iter()
+        .map(|(x, y)| (c + RADIUS * x, c - RADIUS * y))
+        .collect();
+    try!(writeln!(writer,
+                  r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{0}" viewBox="0 0 {0} {0}">"#,
+                  SVG_SIZE));
+    if markers {
+        try!(writer.write_all(MARKERS.as_bytes()));
+    }
+    try!(writeln!(writer, r#"<g stroke="black" stroke-width="1.5">"#));
+    for e in net.raw_edges() {
+        let (u, v) = (e.source(), e.target());
+        if u == v {
+            continue;
+        }
+        let ((x1, y1), (x2, y2)) = (pos[u.index()], pos[v.index()]);
+        let len = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+        let (dx, dy) = ((x2 - x1) / len, (y2 - y1) / len);
+        // Each edge of a mutual pair keeps to its own right.
+        let side = if D::is_directed() && net.find_edge(v, u).is_some() { MUTUAL_OFFSET } else { 0. };
+        let (ox, oy) = (-dy * side, dx * side);
+        // Stop short of the nodes, so the arrowheads are not hidden.
+        let gap = if D::is_directed() { NODE_RADIUS + 1. } else { 0. };
+        try!(write!(writer, r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}""#,
+                    x1 + ox + dx * gap, y1 + oy + dy * gap, x2 + ox - dx * gap, y2 + oy - dy * gap));
+        if D::is_directed() {
+            try!(write!(writer, r#" marker-end="url(#{})""#, arrowhead(e.weight)));
+        }
+        try!(writeln!(writer, "/>"));
+    }
+    try!(writeln!(writer, "</g>"));
+    for (i, &(x, y)) in pos.iter().enumerate() {
+        try!(write!(writer, r#"<circle cx="{:.1}" cy="{:.1}" r="{}""#, x, y, NODE_RADIUS));
+        match colours.get(i) {
+            Some(&c) if c > 0 => try!(write!(writer, r#" fill="{}""#, NODE_FILLS[(c as usize - 1) % NODE_FILLS.len()])),
+            _ => {}
+        }
+        try!(writeln!(writer, "/>"));
+    }
+    writeln!(writer, "</svg>")
+}
+
+#[test]
+fn test_circular_layout() {
+    let layout = circular_layout(4);
+    let expected = [(0., 1.), (1., 0.), (0., -1.), (-1., 0.)];
+    for (&(x, y), &(ex, ey)) in layout.iter().zip(&expected) {
+        assert!((x - ex).abs() < 1e-9 && (y - ey).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn test_write_svg() {
+    let mut net = Network::new();
+    let (a, b, c) = (net.add_node("a".to_string()), net.add_node("b".to_string()), net.add_node("c".to_string()));
+    net.add_edge(a, b, 1);
+    net.add_edge(b, a, 2);
+    net.add_edge(b, c, 3);
+    net.add_edge(c, c, 1);
+    let mut svg = Vec::new();
+    write_svg(&mut svg, &net).unwrap();
+    let svg = String::from_utf8(svg).unwrap();
+    assert!(svg.starts_with("<svg "));
+    assert!(svg.ends_with("</svg>\n"));
+    assert_eq!(3, svg.matches("<line ").count());
+    assert_eq!(3, svg.matches("\n<circle ").count());
+    for marker in &["normal", "tee", "diamond"] {
+        assert_eq!(1, svg.matches(&format!("marker-end=\"url(#{})\"", marker)).count());
+    }
+    assert_eq!(1, svg.matches("<defs>").count());
+    // Node a is at the top.
+    assert!(svg.contains(r#"<circle cx="60.0" cy="15.0" r="5"/>"#));
+
+    let mut svg = Vec::new();
+    write_svg(&mut svg, &with_directedness::<Undirected>(&net)).unwrap();
+    let svg = String::from_utf8(svg).unwrap();
+    assert_eq!(2, svg.matches("<line ").count());
+    assert!(!svg.contains("marker"));
+
+    let mut svg = Vec::new();
+    write_coloured_svg(&mut svg, &net, &[0, 2, 8]).unwrap();
+    let svg = String::from_utf8(svg).unwrap();
+    assert!(svg.contains(r#"<circle cx="60.0" cy="15.0" r="5"/>"#));
+    assert_eq!(2, svg.matches(r##"fill="#d62728""##).count());
+
+    // Drawings inline in a page share one copy of the markers.
+    let mut page = Vec::new();
+    write_svg_markers(&mut page).unwrap();
+    write_svg_body(&mut page, &net, &[]).unwrap();
+    write_svg_body(&mut page, &net, &[]).unwrap();
+    let page = String::from_utf8(page).unwrap();
+    assert_eq!(3, page.matches("<svg ").count());
+    assert_eq!(1, page.matches("<defs>").count());
+    for marker in &["normal", "tee", "diamond", "circle"] {
+        assert_eq!(1, page.matches(&format!("id=\"{}\"", marker)).count());
+        assert_eq!(svg.matches(&format!("url(#{})", marker)).count() * 2,
+                   page.matches(&format!("url(#{})", marker)).count());
+    }
+}
@@ -0,0 +1,134 @@
+use motifs::*;
+use network::*;
+use render::*;
+use stats::*;
+
+use std::io;
+use std::io::prelude::*;
+#[cfg(test)]
+use std::iter::FromIterator;
+
+const STYLE: &'static str = "
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { padding: 0.2em 0.8em; text-align: right; border-bottom: 1px solid #ddd; }
+th { cursor: pointer; background: #f4f4f4; }
+th.asc::after { content: ' \\25b2'; }
+th.desc::after { content: ' \\25bc'; }
+";
+
+/// Sorts the table by a column when its header is clicked, by each cell's `data-sort`.
+const SCRIPT: &'static str = "
+document.querySelectorAll('th').forEach(function (th, i) {
+  th.addEventListener('click', function () {
+    var body = th.closest('table').tBodies[0];
+    var asc = !th.classList.contains('asc');
+    th.parentNode.querySelectorAll('th').forEach(function (h) { h.className = ''; });
+    th.className = asc ? 'asc' : 'desc';
+    Array.prototype.slice.call(body.rows).sort(function (a, b) {
+      var x = parseFloat(a.cells[i].dataset.sort), y = parseFloat(b.cells[i].dataset.sort);
+      return asc ? x - y : y - x;
+    }).forEach(function (row) { body.appendChild(row); });
+  });
+});
+";
+
+const CHART_HEIGHT: f64 = 200.0;
+const BAR_WIDTH: f64 = 24.0;
+
+/// Writes `stats` for the k-node motifs of a network as one HTML page, with nothing to fetch: a
+/// drawing of each motif, a table of its statistics that sorts by any column, and a bar chart of
+/// the significance profile. `title` names the network; `bits` is the width of the `MotifId`
+/// digits, so the motifs can be drawn with any node colours they have.
+pub fn write_report<W: Write, D: Directedness>(mut writer: W,
+                                               title: &str,
+                                               k: usize,
+                                               bits: usize,
+                                               stats: &MotifStatsMap)
+                                               -> io::Result<()> {
+    let title = escape(title);
+    try!(writeln!(writer, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"));
+    try!(writeln!(writer, "<title>{}: {}-node motifs</title>\n<style>{}</style>\n</head>\n<body>",
+                  title, k, STYLE));
+    try!(writeln!(writer, "<h1>{}: {}-node motifs</h1>", title, k));
+    if D::is_directed() {
+        try!(write_svg_markers(&mut writer));
+    }
+
+    try!(writeln!(writer, "<h2>Significance profile</h2>"));
+    try!(write_profile(&mut writer, stats));
+
+    try!(writeln!(writer, "<h2>Motifs</h2>\n<table>\n<thead><tr>"));
+    for h in &["Motif", "ID", "Original", "Mean \u{b1} SD", "Z", "P", "Concentration", "SP"] {
+        try!(write!(writer, "<th>{}</th>", h));
+    }
+    try!(writeln!(writer, "</tr></thead>\n<tbody>"));
+    for (i, (id, s)) in stats.iter().enumerate() {
+        let colours = id_node_colours::<D>(k, bits, id);
+        try!(write!(writer, "<tr><td data-sort=\"{}\">", i));
+        try!(write_svg_body(&mut writer, &id_to_network::<D>(k, bits, id), &colours));
+        try!(writeln!(writer,
+                      "</td><td data-sort=\"{0}\">{1}</td><td data-sort=\"{2}\">{2}</td>\
+                       <td data-sort=\"{3}\">{3:.2} \u{b1} {4:.2}</td><td data-sort=\"{5}\">{5:.2}</td>\
+                       <td data-sort=\"{6}\">{6:.3}</td><td data-sort=\"{7}\">{7:.4}</td>\
+                       <td data-sort=\"{8}\">{8:.3}</td></tr>",
+                      i, id, s.original, s.mean, s.sd, s.z_score, s.p_value, s.concentration, s.significance));
+    }
+    try!(writeln!(writer, "</tbody>\n</table>"));
+    try!(writeln!(writer, "<script>{}</script>\n</body>\n</html>", SCRIPT));
+    writer.flush()
+}
+
+/// A bar for each motif's entry in the significance profile, up for over-represented motifs and
+/// down for under-represented ones, with its ID below.
+fn write_profile<W: Write>(mut writer: W, stats: &MotifStatsMap) -> io::Result<()> {
+    let width = BAR_WIDTH * (stats.len() as f64 + 1.);
+    let (mid, scale) = (CHART_HEIGHT / 2., CHART_HEIGHT / 2. - 10.);
+    try!(writeln!(writer,
+                  r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+                  width, CHART_HEIGHT + 80.));
+    try!(writeln!(writer, r#"<line x1="0" y1="{0}" x2="{1}" y2="{0}" stroke="black"/>"#, mid, width));
+    for (i, (id, s)) in stats.iter().enumerate() {
+        let x = BAR_WIDTH * (i as f64 + 0.5);
+        let h = s.significance.abs() * scale;
+        let (y, fill) = if s.significance >= 0. { (mid - h, "#1f77b4") } else { (mid, "#d62728") };
+        try!(writeln!(writer,
+                      r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}"><title>{}: {:.3}</title></rect>"#,
+                      x + 2., y, BAR_WIDTH - 4., h, fill, id, s.significance));
+        let (lx, ly) = (x + BAR_WIDTH / 2., CHART_HEIGHT + 5.);
+        try!(writeln!(writer,
+                      r#"<text x="{0:.1}" y="{1:.1}" font-size="9" transform="rotate(60 {0:.1} {1:.1})">{2}</text>"#,
+                      lx, ly, id));
+    }
+    writeln!(writer, "</svg>")
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[test]
+fn test_write_report() {
+    let net = network_from_paper();
+    let original = enumerate_subgraphs(3, &net).unwrap();
+    let ensemble = ::randomize::ensemble(&net, 5, 10, 1);
+    let ensemble = Vec::from_iter(ensemble.iter().map(|r| enumerate_subgraphs(3, r).unwrap()));
+    let stats = motif_stats(&original, &ensemble);
+
+    let mut html = Vec::new();
+    write_report::<_, Directed>(&mut html, "paper <1>", 3, 2, &stats).unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert!(html.contains("<h1>paper &lt;1&gt;: 3-node motifs</h1>"));
+    // One row and one drawing per motif, one bar in the profile, and the markers once.
+    assert_eq!(stats.len(), html.matches("<tr><td data-sort=").count());
+    assert_eq!(stats.len() + 2, html.matches("<svg ").count());
+    assert_eq!(1, html.matches("<defs>").count());
+    assert_eq!(stats.len(), html.matches("<rect ").count());
+    for (id, s) in &stats {
+        assert!(html.contains(&format!("<td data-sort=\"{0}\">{0}</td>", s.original)));
+        assert!(html.contains(&format!("<title>{}: {:.3}</title>", id, s.significance)));
+    }
+    // Nothing from elsewhere.
+    assert!(!html.contains("src="));
+    assert!(!html.contains("href="));
+}
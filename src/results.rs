@@ -0,0 +1,680 @@
+use error::*;
+use motifs::*;
+use network::*;
+use stats::*;
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The results of one motif analysis, to write out as JSON and read back.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analysis {
+    pub input: Input,
+    pub k: usize,
+    /// What was run: `count`, `count-non-induced` for a count with non-induced counts too, or
+    /// `significance`.
+    pub mode: String,
+    pub directed: bool,
+    /// Every motif found, in `MotifId` order.
+    pub motifs: Vec<MotifResult>,
+    /// Wall-clock seconds the analysis took.
+    pub runtime: f64,
+}
+
+/// What an analysis was run on.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Input {
+    pub path: String,
+    pub nodes: usize,
+    pub edges: usize,
+    /// The number of random networks compared against, 0 if none.
+    pub ensemble: usize,
+    /// The names of the node categories, colour `c` being `node_categories[c - 1]`, or empty if
+    /// the nodes were not coloured.
+    pub node_categories: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MotifResult {
+    pub id: MotifId,
+    /// The motif in canonical order: row `i` column `j` is the `EdgeType` of `i -> j`, or 0.
+    /// Symmetric for undirected motifs.
+    pub adjacency: Vec<Vec<EdgeType>>,
+    /// The colour of each node, or empty if the nodes were not coloured.
+    pub node_colours: Vec<NodeColour>,
+    pub count: usize,
+    /// The number of the motif's copies counting those with extra edges between their nodes, if
+    /// non-induced counts were asked for.
+    pub non_induced: Option<usize>,
+    /// How the count compares with the ensemble, if there was one.
+    pub stats: Option<MotifStats>,
+}
+
+impl MotifResult {
+    /// The result for the k-node motif `id`, with `bits` wide digits. Node colours are kept if
+    /// `coloured`.
+    pub fn new<D: Directedness>(k: usize, bits: usize, coloured: bool, id: &MotifId, count: usize) -> MotifResult {
+        let net = id_to_network::<D>(k, bits, id);
+        let adjacency = (0..k).map(|i| {
+            (0..k).map(|j| net.find_edge(NodeIndex::new(i), NodeIndex::new(j)).map_or(0, |e| net[e])).collect()
+        }).collect();
+        MotifResult {
+            id: id.clone(),
+            adjacency: adjacency,
+            node_colours: if coloured { id_node_colours::<D>(k, bits, id) } else { Vec::new() },
+            count: count,
+            non_induced: None,
+            stats: None,
+        }
+    }
+}
+
+impl Analysis {
+    /// The motifs of `counts`, without statistics.
+    pub fn from_counts<D: Directedness>(input: Input,
+                                        k: usize,
+                                        bits: usize,
+                                        coloured: bool,
+                                        counts: &MotifFreq)
+                                        -> Analysis {
+        Analysis {
+            input: input,
+            k: k,
+            mode: "count".to_string(),
+            directed: D::is_directed(),
+            motifs: counts.iter().map(|(id, count)| MotifResult::new::<D>(k, bits, coloured, id, *count)).collect(),
+            runtime: 0.0,
+        }
+    }
+
+    /// The motifs of `non_induced`, from `non_induced_counts`, with their induced `counts`. This
+    /// includes motifs only found as part of bigger ones, with a count of 0.
+    pub fn from_non_induced_counts<D: Directedness>(input: Input,
+                                                    k: usize,
+                                                    bits: usize,
+                                                    coloured: bool,
+                                                    counts: &MotifFreq,
+                                                    non_induced: &MotifFreq)
+                                                    -> Analysis {
+        Analysis {
+            input: input,
+            k: k,
+            mode: "count-non-induced".to_string(),
+            directed: D::is_directed(),
+            motifs: non_induced.iter()
+                .map(|(id, n)| {
+                    let count = counts.get(id).cloned().unwrap_or(0);
+                    MotifResult { non_induced: Some(*n), ..MotifResult::new::<D>(k, bits, coloured, id, count) }
+                })
+                .collect(),
+            runtime: 0.0,
+        }
+    }
+
+    /// The motifs of `stats`, including those only found in the ensemble.
+    pub fn from_stats<D: Directedness>(input: Input,
+                                       k: usize,
+                                       bits: usize,
+                                       coloured: bool,
+                                       stats: &MotifStatsMap)
+                                       -> Analysis {
+        Analysis {
+            input: input,
+            k: k,
+            mode: "significance".to_string(),
+            directed: D::is_directed(),
+            motifs: stats.iter()
+                .map(|(id, s)| MotifResult { stats: Some(*s), ..MotifResult::new::<D>(k, bits, coloured, id, s.original) })
+                .collect(),
+            runtime: 0.0,
+        }
+    }
+
+    /// The count of each motif found in the network.
+    pub fn counts(&self) -> MotifFreq {
+        self.motifs.iter().filter(|m| m.count > 0).map(|m| (m.id.clone(), m.count)).collect()
+    }
+}
+
+/// Writes `analysis` as a JSON object. Motif IDs are strings, as they are often too big for the
+/// numbers JSON readers take, and statistics that are not numbers are `null`.
+pub fn write_json<W: Write>(mut writer: W, analysis: &Analysis) -> io::Result<()> {
+    let input = &analysis.input;
+    try!(writeln!(writer, "{{"));
+    try!(writeln!(writer,
+                  "  \"input\": {{\"path\": {}, \"nodes\": {}, \"edges\": {}, \"ensemble\": {}, \
+                   \"node_categories\": {}}},",
+                  json_string(&input.path), input.nodes, input.edges, input.ensemble,
+                  json_array(&input.node_categories, |c| json_string(c))));
+    try!(writeln!(writer, "  \"k\": {},", analysis.k));
+    try!(writeln!(writer, "  \"mode\": {},", json_string(&analysis.mode)));
+    try!(writeln!(writer, "  \"directed\": {},", analysis.directed));
+    try!(writeln!(writer, "  \"runtime\": {},", json_number(analysis.runtime)));
+    try!(write!(writer, "  \"motifs\": ["));
+    for (i, m) in analysis.motifs.iter().enumerate() {
+        try!(write!(writer, "{}\n    {{\"id\": \"{}\", \"adjacency\": {}, \"node_colours\": {}, \"count\": {}",
+                    if i > 0 { "," } else { "" }, m.id,
+                    json_array(&m.adjacency, |row| json_array(row, |w| w.to_string())),
+                    json_array(&m.node_colours, |c| c.to_string()), m.count));
+        if let Some(n) = m.non_induced {
+            try!(write!(writer, ", \"non_induced\": {}", n));
+        }
+        if let Some(ref s) = m.stats {
+            try!(write!(writer,
+                        ", \"stats\": {{\"mean\": {}, \"sd\": {}, \"z_score\": {}, \"p_value\": {}, \
+                         \"concentration\": {}, \"significance\": {}}}",
+                        json_number(s.mean), json_number(s.sd), json_number(s.z_score), json_number(s.p_value),
+                        json_number(s.concentration), json_number(s.significance)));
+        }
+        try!(write!(writer, "}}"));
+    }
+    if !analysis.motifs.is_empty() {
+        try!(write!(writer, "\n  "));
+    }
+    try!(writeln!(writer, "]\n}}"));
+    writer.flush()
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `items` as a JSON array, each written by `f`.
+fn json_array<T, F: Fn(&T) -> String>(items: &[T], f: F) -> String {
+    let items: Vec<_> = items.iter().map(f).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn json_number(x: f64) -> String {
+    if x.is_finite() { x.to_string() } else { "null".to_string() }
+}
+
+/// A parsed JSON value. Numbers keep their text, so big counts are not rounded.
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+/// How deeply values may nest, so bad input cannot overflow the stack. The numbers in an
+/// adjacency matrix are five deep.
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+    /// How many values the parser is in.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, msg: &str) -> Result<T> {
+        Err(Error::Parse { line: None, msg: format!("invalid JSON at byte {}: {}", self.pos, msg) })
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.s.len() && (self.s[self.pos] as char).is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.s.get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<()> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(&format!("expected {:?}", c as char))
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json> {
+        if self.s[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            self.error("unknown literal")
+        }
+    }
+
+    fn value(&mut self) -> Result<Json> {
+        if self.depth == MAX_DEPTH {
+            return self.error("nested too deeply");
+        }
+        self.depth += 1;
+        let value = self.unnested_value();
+        self.depth -= 1;
+        value
+    }
+
+    /// A value, with `depth` already counting it.
+    fn unnested_value(&mut self) -> Result<Json> {
+        match self.peek() {
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(try!(self.value()));
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => return self.error("expected ',' or ']'"),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = BTreeMap::new();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    if self.peek() != Some(b'"') {
+                        return self.error("expected a field name");
+                    }
+                    let name = try!(self.string());
+                    try!(self.expect(b':'));
+                    fields.insert(name, try!(self.value()));
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(fields));
+                        }
+                        _ => return self.error("expected ',' or '}'"),
+                    }
+                }
+            }
+            Some(c) if c == b'-' || c.is_ascii_digit() => self.number(),
+            Some(_) => self.error("unexpected character"),
+            None => self.error("unexpected end"),
+        }
+    }
+
+    /// A number as JSON writes them: no leading zeros or `+`, and digits on both sides of a `.`.
+    fn number(&mut self) -> Result<Json> {
+        let start = self.pos;
+        if self.s[self.pos] == b'-' {
+            self.pos += 1;
+        }
+        if self.s.get(self.pos) == Some(&b'0') {
+            self.pos += 1;
+            if self.digits() > 0 {
+                return self.error("leading zero in number");
+            }
+        } else if self.digits() == 0 {
+            return self.error("invalid number");
+        }
+        if self.s.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            if self.digits() == 0 {
+                return self.error("invalid number");
+            }
+        }
+        if let Some(&b'e') | Some(&b'E') = self.s.get(self.pos) {
+            self.pos += 1;
+            if let Some(&b'+') | Some(&b'-') = self.s.get(self.pos) {
+                self.pos += 1;
+            }
+            if self.digits() == 0 {
+                return self.error("invalid number");
+            }
+        }
+        Ok(Json::Number(String::from_utf8_lossy(&self.s[start..self.pos]).into_owned()))
+    }
+
+    /// Skips the decimal digits at `pos`, giving how many there were.
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while self.pos < self.s.len() && self.s[self.pos].is_ascii_digit() {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn string(&mut self) -> Result<String> {
+        try!(self.expect(b'"'));
+        let mut bytes = Vec::new();
+        loop {
+            let c = match self.s.get(self.pos) {
+                Some(c) => *c,
+                None => return self.error("unterminated string"),
+            };
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.s.get(self.pos) {
+                        Some(c) => *c,
+                        None => return self.error("unterminated string"),
+                    };
+                    self.pos += 1;
+                    let c = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = try!(self.hex4());
+                            // Characters outside the BMP are written as a pair of surrogates.
+                            if code >= 0xd800 && code < 0xdc00 && self.s[self.pos..].starts_with(b"\\u") {
+                                self.pos += 2;
+                                let low = try!(self.hex4());
+                                if low < 0xdc00 || low >= 0xe000 {
+                                    return self.error("unpaired surrogate in \\u escape");
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            match ::std::char::from_u32(code) {
+                                Some(c) => c,
+                                None => return self.error("invalid \\u escape"),
+                            }
+                        }
+                        _ => return self.error("invalid escape"),
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                c if c < 0x20 => return self.error("control character in string"),
+                c => bytes.push(c),
+            }
+        }
+        match String::from_utf8(bytes) {
+            Ok(s) => Ok(s),
+            Err(_) => self.error("invalid UTF-8"),
+        }
+    }
+
+    /// The four hex digits of a `\\u` escape.
+    fn hex4(&mut self) -> Result<u32> {
+        let hex = &self.s[self.pos..cmp::min(self.pos + 4, self.s.len())];
+        let mut code = 0;
+        for &c in hex {
+            match (c as char).to_digit(16) {
+                Some(d) => code = code * 16 + d,
+                None => return self.error("invalid \\u escape"),
+            }
+        }
+        if hex.len() < 4 {
+            return self.error("invalid \\u escape");
+        }
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+/// A field of a JSON object, converted by `f`.
+fn field<'a, T, F>(object: &'a BTreeMap<String, Json>, name: &str, f: F) -> Result<T>
+    where F: FnOnce(&'a Json) -> Option<T>
+{
+    object.get(name).and_then(f).ok_or_else(|| invalid(name))
+}
+
+fn invalid(name: &str) -> Error {
+    Error::Parse { line: None, msg: format!("missing or invalid JSON field {:?}", name) }
+}
+
+fn as_object(json: &Json) -> Option<&BTreeMap<String, Json>> {
+    match *json {
+        Json::Object(ref fields) => Some(fields),
+        _ => None,
+    }
+}
+
+fn as_array(json: &Json) -> Option<&Vec<Json>> {
+    match *json {
+        Json::Array(ref items) => Some(items),
+        _ => None,
+    }
+}
+
+fn as_str(json: &Json) -> Option<&str> {
+    match *json {
+        Json::String(ref s) => Some(s),
+        _ => None,
+    }
+}
+
+fn as_number<T: FromStr>(json: &Json) -> Option<T> {
+    match *json {
+        Json::Number(ref text) => T::from_str(text).ok(),
+        _ => None,
+    }
+}
+
+/// Like `as_number`, but `null` for not a number.
+fn as_f64(json: &Json) -> Option<f64> {
+    match *json {
+        Json::Null => Some(f64::NAN),
+        _ => as_number(json),
+    }
+}
+
+fn as_vec<T, F: Fn(&Json) -> Option<T>>(json: &Json, f: F) -> Option<Vec<T>> {
+    as_array(json).and_then(|items| items.iter().map(f).collect())
+}
+
+/// Reads an `Analysis` written by `write_json`. Other fields are ignored.
+pub fn parse_json<R: Read>(mut reader: R) -> Result<Analysis> {
+    let mut s = Vec::new();
+    try!(reader.read_to_end(&mut s));
+    let mut parser = Parser { s: &s, pos: 0, depth: 0 };
+    let json = try!(parser.value());
+    if parser.peek().is_some() {
+        return parser.error("trailing characters");
+    }
+    let top = try!(as_object(&json).ok_or_else(|| invalid("")));
+    let input = try!(field(top, "input", as_object));
+    let mut motifs = Vec::new();
+    for m in try!(field(top, "motifs", as_array)) {
+        let m = try!(as_object(m).ok_or_else(|| invalid("motifs")));
+        let count = try!(field(m, "count", as_number));
+        let non_induced = match m.get("non_induced") {
+            None | Some(&Json::Null) => None,
+            Some(n) => Some(try!(as_number(n).ok_or_else(|| invalid("non_induced")))),
+        };
+        let stats = match m.get("stats") {
+            None | Some(&Json::Null) => None,
+            Some(s) => {
+                let s = try!(as_object(s).ok_or_else(|| invalid("stats")));
+                Some(MotifStats {
+                    original: count,
+                    concentration: try!(field(s, "concentration", as_f64)),
+                    mean: try!(field(s, "mean", as_f64)),
+                    sd: try!(field(s, "sd", as_f64)),
+                    z_score: try!(field(s, "z_score", as_f64)),
+                    p_value: try!(field(s, "p_value", as_f64)),
+                    significance: try!(field(s, "significance", as_f64)),
+                })
+            }
+        };
+        motifs.push(MotifResult {
+            id: try!(field(m, "id", |j| as_str(j).and_then(|s| MotifId::from_str(s).ok()))),
+            adjacency: try!(field(m, "adjacency", |j| as_vec(j, |row| as_vec(row, as_number)))),
+            node_colours: try!(field(m, "node_colours", |j| as_vec(j, as_number))),
+            count: count,
+            non_induced: non_induced,
+            stats: stats,
+        });
+    }
+    Ok(Analysis {
+        input: Input {
+            path: try!(field(input, "path", |j| as_str(j).map(str::to_string))),
+            nodes: try!(field(input, "nodes", as_number)),
+            edges: try!(field(input, "edges", as_number)),
+            ensemble: try!(field(input, "ensemble", as_number)),
+            node_categories: try!(field(input, "node_categories", |j| {
+                as_vec(j, |c| as_str(c).map(str::to_string))
+            })),
+        },
+        k: try!(field(top, "k", as_number)),
+        mode: try!(field(top, "mode", |j| as_str(j).map(str::to_string))),
+        directed: try!(field(top, "directed", |j| match *j {
+            Json::Bool(b) => Some(b),
+            _ => None,
+        })),
+        motifs: motifs,
+        runtime: try!(field(top, "runtime", as_f64)),
+    })
+}
+
+pub fn read_json<P: AsRef<Path>>(path: P) -> Result<Analysis> {
+    let file = try!(File::open(path));
+    parse_json(io::BufReader::new(file))
+}
+
+#[cfg(test)]
+fn roundtrip(analysis: &Analysis) -> Analysis {
+    let mut json = Vec::new();
+    write_json(&mut json, analysis).unwrap();
+    parse_json(&json[..]).unwrap()
+}
+
+#[test]
+fn test_json_roundtrip() {
+    let net = network_from_paper();
+    let input = Input {
+        path: "graphs/paper \"1\".txt".to_string(),
+        nodes: 16,
+        edges: net.edge_count(),
+        ensemble: 3,
+        node_categories: Vec::new(),
+    };
+    let counts = enumerate_subgraphs(3, &net).unwrap();
+    let analysis = Analysis { runtime: 0.25, ..Analysis::from_counts::<Directed>(input.clone(), 3, 2, false, &counts) };
+    assert_eq!(analysis, roundtrip(&analysis));
+    assert_eq!(counts, roundtrip(&analysis).counts());
+    // The adjacency is the canonical motif's.
+    for m in &analysis.motifs {
+        let motif: Network = id_to_network(3, 2, &m.id);
+        for e in motif.raw_edges() {
+            assert_eq!(e.weight, m.adjacency[e.source().index()][e.target().index()]);
+        }
+        assert_eq!(motif.edge_count(), m.adjacency.iter().flatten().filter(|w| **w > 0).count());
+    }
+
+    let ensemble = ::randomize::ensemble(&net, 3, 10, 1);
+    let ensemble: Vec<_> = ensemble.iter().map(|r| enumerate_subgraphs(3, r).unwrap()).collect();
+    let stats = motif_stats(&counts, &ensemble);
+    let analysis = Analysis::from_stats::<Directed>(input.clone(), 3, 2, false, &stats);
+    assert_eq!(stats.len(), analysis.motifs.len());
+    assert_eq!(analysis, roundtrip(&analysis));
+    assert_eq!(counts, analysis.counts());
+
+    // Undirected motifs have symmetric adjacency, and coloured ones keep their node colours.
+    let mut coloured = MotifFreq::new();
+    coloured.insert(MotifId::from(1 | 3 << 2 | 2 << 4), 7);
+    let input = Input { node_categories: vec!["tf".to_string(), "gene".to_string(), "mi\"RNA".to_string()], ..input };
+    let analysis = Analysis::from_counts::<Undirected>(input, 2, 2, true, &coloured);
+    assert_eq!(vec![vec![0, 1], vec![1, 0]], analysis.motifs[0].adjacency);
+    assert_eq!(vec![3, 2], analysis.motifs[0].node_colours);
+    assert!(!analysis.directed);
+    assert_eq!(analysis, roundtrip(&analysis));
+
+    // Non-induced counts include motifs only found inside bigger ones, such as the parts of a
+    // feed-forward loop with one edge back.
+    let mut counts = MotifFreq::new();
+    counts.insert(MotifId::from(21508), 1);
+    let mut cache = ::canon::CanonCache::<Directed>::new(3, 2).unwrap();
+    let non_induced = ::inclusion::non_induced_counts(&counts, &mut cache);
+    let analysis = Analysis::from_non_induced_counts::<Directed>(Input::default(), 3, 2, false, &counts, &non_induced);
+    assert_eq!("count-non-induced", analysis.mode);
+    assert_eq!(8, analysis.motifs.len());
+    for m in &analysis.motifs {
+        assert_eq!(Some(non_induced[&m.id]), m.non_induced);
+        assert_eq!(counts.get(&m.id).cloned().unwrap_or(0), m.count);
+    }
+    assert_eq!(analysis, roundtrip(&analysis));
+    assert_eq!(counts, analysis.counts());
+}
+
+#[test]
+fn test_parse_json() {
+    let json = r#"{"input": {"path": "aé\\b", "nodes": 2, "edges": 1, "ensemble": 0, "extra": [1, 2.5e3],
+                             "node_categories": []},
+                   "k": 2, "mode": "count", "directed": true, "runtime": 1e-3,
+                   "motifs": [{"id": "123456789012345678901234567890", "adjacency": [[0, 1], [0, 0]],
+                               "node_colours": [], "count": 18446744073709551615,
+                               "stats": {"mean": 1, "sd": null, "z_score": -2, "p_value": 0.5,
+                                         "concentration": 1, "significance": -1}}]}"#;
+    let analysis = parse_json(json.as_bytes()).unwrap();
+    assert_eq!("a\u{e9}\\b", analysis.input.path);
+    assert_eq!(0.001, analysis.runtime);
+    let m = &analysis.motifs[0];
+    assert_eq!("123456789012345678901234567890", m.id.to_string());
+    assert_eq!(usize::MAX, m.count);
+    let s = m.stats.unwrap();
+    assert!(s.sd.is_nan());
+    assert_eq!(-2.0, s.z_score);
+
+    for bad in &["", "{", "{\"k\": 2}", "[1, 2]", "{\"input\": {}} x", "{\"k\": 2,}"] {
+        assert!(parse_json(bad.as_bytes()).is_err(), "{:?}", bad);
+    }
+
+    let string = |s: &str| Parser { s: s.as_bytes(), pos: 0, depth: 0 }.string();
+    assert_eq!("\u{1f600}\u{e9}", string(r#""\uD83D\ude00\u00e9""#).unwrap());
+    for bad in &[r#""\uD83D""#, r#""\uD83Dx""#, r#""\uD83D\u0041""#, r#""\uDE00""#, r#""\u12""#, r#""\u+041""#,
+                 "\"a\nb\"", "\"\u{1}\""] {
+        assert!(string(bad).is_err(), "{:?}", bad);
+    }
+
+    let number = |s: &str| match (Parser { s: s.as_bytes(), pos: 0, depth: 0 }).value() {
+        Ok(Json::Number(n)) => Some(n),
+        _ => None,
+    };
+    for good in &["0", "-0", "10", "0.5", "-1.25e-3", "1E+2", "0e0"] {
+        assert_eq!(Some(good.to_string()), number(good));
+    }
+    for bad in &["01", "-01", "00", "1.", ".5", "+1", "-", "1e", "1e+", "-.5"] {
+        assert_eq!(None, number(bad), "{:?}", bad);
+    }
+
+    // Too deep to be results, and would otherwise run out of stack.
+    let deep = format!("{}{}", "[".repeat(100000), "]".repeat(100000));
+    match parse_json(deep.as_bytes()) {
+        Err(Error::Parse { ref msg, .. }) if msg.contains("nested too deeply") => {}
+        r => panic!("expected a nesting error, got {:?}", r),
+    }
+}
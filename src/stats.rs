@@ -0,0 +1,121 @@
+use motifs::*;
+
+use std::collections::BTreeMap;
+
+/// How a motif's count in a network compares with its counts in a randomized ensemble.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MotifStats {
+    pub original: usize,
+    /// The motif's share of all the k-subgraphs of the original network.
+    pub concentration: f64,
+    pub mean: f64,
+    /// Sample standard deviation of the ensemble counts.
+    pub sd: f64,
+    /// `(original - mean) / sd`, or 0 when the ensemble counts do not vary.
+    pub z_score: f64,
+    /// The fraction of networks, counting the original, with at least the original count:
+    /// `(1 + #{random >= original}) / (1 + N)`.
+    pub p_value: f64,
+    /// This motif's entry in the significance profile, the Z-scores normalized to unit length
+    /// (Milo et al. 2004).
+    pub significance: f64,
+}
+
+pub type MotifStatsMap = BTreeMap<MotifId, MotifStats>;
+
+/// Statistics for every motif found in the original network or any network of the ensemble.
+pub fn motif_stats(original: &MotifFreq, ensemble: &[MotifFreq]) -> MotifStatsMap {
+    let mut ids: Vec<_> = original.keys().collect();
+    for freq in ensemble {
+        ids.extend(freq.keys());
+    }
+    ids.sort();
+    ids.dedup();
+
+    let n = ensemble.len() as f64;
+    let total = original.values().sum::<usize>();
+    let mut stats = BTreeMap::new();
+    for id in ids {
+        let count = *original.get(id).unwrap_or(&0);
+        let counts: Vec<_> = ensemble.iter().map(|freq| *freq.get(id).unwrap_or(&0)).collect();
+        let mean = if counts.is_empty() { 0.0 } else { counts.iter().sum::<usize>() as f64 / n };
+        let sd = if counts.len() < 2 {
+            0.0
+        } else {
+            (counts.iter().map(|&c| (c as f64 - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+        };
+        let z_score = if sd > 0.0 { (count as f64 - mean) / sd } else { 0.0 };
+        let at_least = counts.iter().filter(|&&c| c >= count).count();
+        stats.insert(id.clone(), MotifStats {
+            original: count,
+            concentration: if total > 0 { count as f64 / total as f64 } else { 0.0 },
+            mean: mean,
+            sd: sd,
+            z_score: z_score,
+            p_value: (1 + at_least) as f64 / (1.0 + n),
+            significance: 0.0,
+        });
+    }
+
+    let norm = stats.values().map(|s| s.z_score * s.z_score).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        for s in stats.values_mut() {
+            s.significance = s.z_score / norm;
+        }
+    }
+    stats
+}
+
+#[cfg(test)]
+fn freq(counts: &[(u64, usize)]) -> MotifFreq {
+    counts.iter().map(|&(id, c)| (MotifId::from(id), c)).collect()
+}
+
+#[test]
+fn test_motif_stats() {
+    let original = freq(&[(1, 10), (2, 5), (3, 7)]);
+    let ensemble = vec![freq(&[(1, 2), (2, 5), (3, 7), (4, 1)]),
+                        freq(&[(1, 4), (2, 7), (3, 7)]),
+                        freq(&[(1, 6), (2, 6), (3, 7), (4, 3)])];
+    let stats = motif_stats(&original, &ensemble);
+    assert_eq!(4, stats.len());
+
+    let s = stats[&MotifId::from(1)];
+    assert_eq!(10, s.original);
+    assert!((s.concentration - 10.0 / 22.0).abs() < 1e-12);
+    assert_eq!(4.0, s.mean);
+    assert_eq!(2.0, s.sd);
+    assert_eq!(3.0, s.z_score);
+    assert_eq!(0.25, s.p_value);
+
+    let s = stats[&MotifId::from(2)];
+    assert_eq!(6.0, s.mean);
+    assert_eq!(1.0, s.sd);
+    assert_eq!(-1.0, s.z_score);
+    assert_eq!(1.0, s.p_value);
+
+    // Constant in the ensemble: no Z-score, and every random network ties.
+    let s = stats[&MotifId::from(3)];
+    assert_eq!(0.0, s.sd);
+    assert_eq!(0.0, s.z_score);
+    assert_eq!(1.0, s.p_value);
+
+    // Only in the ensemble.
+    let s = stats[&MotifId::from(4)];
+    assert_eq!(0, s.original);
+    assert_eq!(0.0, s.concentration);
+    assert!((s.mean - 4.0 / 3.0).abs() < 1e-12);
+    assert_eq!(1.0, s.p_value);
+
+    let norm: f64 = stats.values().map(|s| s.significance * s.significance).sum();
+    assert!((norm - 1.0).abs() < 1e-12);
+    assert!((stats[&MotifId::from(1)].significance -
+             3.0 / (9.0 + 1.0 + stats[&MotifId::from(4)].z_score.powi(2)).sqrt()).abs() < 1e-12);
+}
+
+#[test]
+fn test_motif_stats_empty_ensemble() {
+    let stats = motif_stats(&freq(&[(1, 3)]), &[]);
+    let s = stats[&MotifId::from(1)];
+    assert_eq!((0.0, 0.0, 0.0, 1.0, 0.0), (s.mean, s.sd, s.z_score, s.p_value, s.significance));
+}
@@ -0,0 +1,401 @@
+use error::*;
+use motifs::*;
+use nauty::*;
+use network::*;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::iter::FromIterator;
+#[cfg(test)]
+use std::cmp;
+
+/// The time of an event, in whatever units the data use.
+pub type Timestamp = i64;
+
+/// An interaction between two nodes at one moment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub source: NodeIndex,
+    pub target: NodeIndex,
+    pub edge_type: EdgeType,
+    pub time: Timestamp,
+}
+
+/// A directed network whose edges are timestamped events.
+#[derive(Clone, Debug, Default)]
+pub struct TemporalNetwork {
+    /// Every node, and an edge for each pair that ever interacts, with the types of all its
+    /// events.
+    pub net: Network,
+    /// The events in the order they were added.
+    pub events: Vec<Event>,
+}
+
+impl TemporalNetwork {
+    pub fn new() -> TemporalNetwork {
+        TemporalNetwork::default()
+    }
+
+    pub fn add_node(&mut self, name: String) -> NodeIndex {
+        self.net.add_node(name)
+    }
+
+    /// Adds an event after all the others, whatever its time.
+    pub fn add_event(&mut self, source: NodeIndex, target: NodeIndex, edge_type: EdgeType, time: Timestamp) {
+        match self.net.find_edge(source, target) {
+            Some(e) => self.net[e] |= edge_type,
+            None => {
+                self.net.add_edge(source, target, edge_type);
+            }
+        }
+        self.events.push(Event { source: source, target: target, edge_type: edge_type, time: time });
+    }
+
+    /// Puts the events in time order, keeping the order they were added in for events at the
+    /// same time.
+    pub fn sort_events(&mut self) {
+        self.events.sort_by_key(|e| e.time);
+    }
+}
+
+/// A δ-temporal motif: a static motif, and the order its edges' events happen in.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TemporalMotif {
+    /// The `MotifId` of the nodes with an edge for each pair that has events, with the types of
+    /// all of them.
+    pub motif: MotifId,
+    /// The events in time order, as `(source, target, type)` with the motif's nodes in canonical
+    /// order. Of the orders its automorphisms give, this is the smallest.
+    pub events: Vec<(usize, usize, EdgeType)>,
+}
+
+/// Written as the static motif and then the events, like `4164:0>1,1>2,0>2`. Types other than
+/// activation follow their event, as in `0>1/2`.
+impl fmt::Display for TemporalMotif {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}:", self.motif));
+        for (i, &(u, v, w)) in self.events.iter().enumerate() {
+            try!(write!(f, "{}{}>{}", if i > 0 { "," } else { "" }, u, v));
+            if w != 1 {
+                try!(write!(f, "/{}", w));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub type TemporalMotifFreq = BTreeMap<TemporalMotif, usize>;
+
+/// Counts the δ-temporal motifs of `net` (Paranjape, Benson & Leskovec 2017): sequences of `l`
+/// events, in time order, that all happen within `delta` of the first, and that between them
+/// connect exactly `k` nodes. Events at the same time are taken in the order they were added.
+///
+/// Each sequence is tried by extending it with later events in the window, so the cost grows
+/// with the number of events within `delta` of each other to the power `l - 1`.
+pub fn temporal_motifs(net: &TemporalNetwork, k: usize, l: usize, delta: Timestamp) -> Result<TemporalMotifFreq> {
+    try!(check_size(k));
+    let bits = digit_bits(colour_count(&net.net));
+    try!(check_edge_types(&net.net, bits));
+    let mut sorted = Vec::new();
+    let events = if net.events.windows(2).all(|e| e[0].time <= e[1].time) {
+        &net.events[..]
+    } else {
+        sorted.extend_from_slice(&net.events);
+        sorted.sort_by_key(|e| e.time);
+        &sorted[..]
+    };
+    let mut search = Search {
+        events: events,
+        k: k,
+        l: l,
+        delta: delta,
+        bits: bits,
+        chosen: Vec::with_capacity(l),
+        nodes: Vec::with_capacity(k),
+        classes: HashMap::new(),
+        counts: TemporalMotifFreq::new(),
+    };
+    if l > 0 {
+        for i in 0..events.len() {
+            search.extend(i);
+        }
+    }
+    Ok(search.counts)
+}
+
+/// How to turn event sequences with one static pattern into a `TemporalMotif`.
+struct Class {
+    motif: MotifId,
+    /// For each automorphism of the canonical motif, where each node of the pattern ends up.
+    maps: Vec<Vec<usize>>,
+}
+
+struct Search<'a> {
+    events: &'a [Event],
+    k: usize,
+    l: usize,
+    delta: Timestamp,
+    bits: usize,
+    chosen: Vec<usize>,
+    /// The nodes of the chosen events, in order of first appearance.
+    nodes: Vec<NodeIndex>,
+    /// By the raw adjacency code of the pattern, or `None` if it is not connected.
+    classes: HashMap<MotifId, Option<Class>>,
+    counts: TemporalMotifFreq,
+}
+
+impl<'a> Search<'a> {
+    /// Tries the sequences that go on with event `i`, which is in the window of the first.
+    fn extend(&mut self, i: usize) {
+        let e = self.events[i];
+        let old_nodes = self.nodes.len();
+        for v in &[e.source, e.target] {
+            if !self.nodes.contains(v) {
+                self.nodes.push(*v);
+            }
+        }
+        self.chosen.push(i);
+        // Each event left can bring in two more nodes at most.
+        let left = self.l - self.chosen.len();
+        if self.nodes.len() <= self.k && self.nodes.len() + 2 * left >= self.k {
+            if left == 0 {
+                if self.nodes.len() == self.k {
+                    self.classify();
+                }
+            } else {
+                for j in i + 1..self.events.len() {
+                    if self.events[j].time - self.events[self.chosen[0]].time > self.delta {
+                        break;
+                    }
+                    self.extend(j);
+                }
+            }
+        }
+        self.chosen.pop();
+        self.nodes.truncate(old_nodes);
+    }
+
+    fn classify(&mut self) {
+        let k = self.k;
+        let index = |v: NodeIndex, nodes: &[NodeIndex]| nodes.iter().position(|u| *u == v).unwrap();
+        let events = Vec::from_iter(self.chosen.iter().map(|i| {
+            let e = self.events[*i];
+            (index(e.source, &self.nodes), index(e.target, &self.nodes), e.edge_type)
+        }));
+        let mut pattern = Network::with_capacity(k, events.len());
+        for i in 0..k {
+            pattern.add_node(i.to_string());
+        }
+        for &(u, v, w) in &events {
+            let (u, v) = (NodeIndex::new(u), NodeIndex::new(v));
+            match pattern.find_edge(u, v) {
+                Some(e) => pattern[e] |= w,
+                None => {
+                    pattern.add_edge(u, v, w);
+                }
+            }
+        }
+        let raw = motif_id(&pattern, self.bits).expect("edge types were checked");
+        let bits = self.bits;
+        let class = self.classes.entry(raw).or_insert_with(|| pattern_class(&pattern, bits));
+        if let Some(ref class) = *class {
+            let key = class.maps.iter()
+                .map(|map| Vec::from_iter(events.iter().map(|&(u, v, w)| (map[u], map[v], w))))
+                .min()
+                .unwrap();
+            *self.counts.entry(TemporalMotif { motif: class.motif.clone(), events: key }).or_insert(0) += 1;
+        }
+    }
+}
+
+fn pattern_class(pattern: &Network, bits: usize) -> Option<Class> {
+    use petgraph::algo::connected_components;
+
+    if connected_components(pattern) != 1 {
+        return None;
+    }
+    let k = pattern.node_count();
+    // `check_size` keeps k within what nauty takes.
+    let a = automorphisms(pattern).expect("k was checked");
+    let mut position = vec![0; k];
+    for (c, v) in a.labelling.iter().enumerate() {
+        position[v.index()] = c;
+    }
+    let motif = motif_id(&pattern.subnet(&a.labelling).expect("labelling is of pattern's nodes"), bits)
+        .expect("edge types were checked");
+
+    // The whole group, from its generators.
+    let identity = Vec::from_iter(0..k);
+    let mut group = HashSet::new();
+    group.insert(identity.clone());
+    let mut todo = vec![identity];
+    let mut maps = Vec::new();
+    while let Some(p) = todo.pop() {
+        for g in &a.generators {
+            let q = Vec::from_iter(p.iter().map(|v| g[*v].index()));
+            if group.insert(q.clone()) {
+                todo.push(q);
+            }
+        }
+        maps.push(Vec::from_iter(p.iter().map(|v| position[*v])));
+    }
+    Some(Class { motif: motif, maps: maps })
+}
+
+#[cfg(test)]
+fn temporal_network(events: &[(usize, usize, EdgeType, Timestamp)]) -> TemporalNetwork {
+    let mut net = TemporalNetwork::new();
+    let n = events.iter().map(|e| cmp::max(e.0, e.1) + 1).max().unwrap_or(0);
+    for i in 0..n {
+        net.add_node(i.to_string());
+    }
+    for &(u, v, w, t) in events {
+        net.add_event(NodeIndex::new(u), NodeIndex::new(v), w, t);
+    }
+    net
+}
+
+#[test]
+fn test_temporal_network() {
+    let mut net = temporal_network(&[(0, 1, 1, 5), (1, 2, 1, 3), (0, 1, 2, 3), (2, 0, 1, 9)]);
+    assert_eq!(vec![5, 3, 3, 9], Vec::from_iter(net.events.iter().map(|e| e.time)));
+    net.sort_events();
+    assert_eq!(vec![3, 3, 5, 9], Vec::from_iter(net.events.iter().map(|e| e.time)));
+    // Same time, so the order they were added in.
+    assert_eq!(NodeIndex::new(1), net.events[0].source);
+    assert_eq!(3, net.net.edge_count());
+    assert_eq!(Some(3), net.net.find_edge(NodeIndex::new(0), NodeIndex::new(1)).map(|e| net.net[e]));
+}
+
+#[test]
+fn test_temporal_motifs() {
+    // A feedforward loop over three time steps.
+    let net = temporal_network(&[(0, 1, 1, 1), (1, 2, 1, 2), (0, 2, 1, 3)]);
+    assert!(temporal_motifs(&net, 3, 3, 1).unwrap().is_empty());
+    let counts = temporal_motifs(&net, 3, 3, 2).unwrap();
+    assert_eq!(1, counts.len());
+    let (motif, count) = counts.iter().next().unwrap();
+    assert_eq!(1, *count);
+    let ffl = canonicalize(net.net.clone()).unwrap();
+    assert_eq!(motif_id(&ffl, 2).unwrap(), motif.motif);
+    // The events are the canonical motif's edges, in order.
+    for (&(u, v, _), e) in motif.events.iter().zip(&net.events) {
+        let (a, b) = (NodeIndex::new(u), NodeIndex::new(v));
+        assert!(ffl.find_edge(a, b).is_some());
+        assert_eq!(&net.net[e.source], &ffl[a]);
+        assert_eq!(&net.net[e.target], &ffl[b]);
+    }
+    // The first two as a path, then a fan out of node 0 and a fan into node 2.
+    let counts = temporal_motifs(&net, 3, 2, 2).unwrap();
+    assert_eq!(vec![1, 1, 1], Vec::from_iter(counts.values().cloned()));
+
+    // Two fans out, one each way round, are the same temporal motif.
+    let net = temporal_network(&[(0, 1, 1, 1), (0, 2, 1, 2), (3, 5, 1, 10), (3, 4, 1, 11)]);
+    let counts = temporal_motifs(&net, 3, 2, 5).unwrap();
+    assert_eq!(vec![2], Vec::from_iter(counts.values().cloned()));
+    // But not if the edge types tell them apart.
+    let net = temporal_network(&[(0, 1, 1, 1), (0, 2, 2, 2), (3, 5, 2, 10), (3, 4, 1, 11)]);
+    assert_eq!(2, temporal_motifs(&net, 3, 2, 5).unwrap().len());
+
+    // Repeated events between the same pair.
+    let net = temporal_network(&[(0, 1, 1, 1), (1, 0, 1, 2), (0, 1, 1, 3)]);
+    let counts = temporal_motifs(&net, 2, 3, 2).unwrap();
+    assert_eq!("20:0>1,1>0,0>1", counts.keys().next().unwrap().to_string());
+    assert_eq!(3, temporal_motifs(&net, 2, 2, 2).unwrap().values().sum::<usize>());
+    assert!(temporal_motifs(&net, 0, 2, 2).is_err());
+}
+
+#[test]
+fn test_temporal_motifs_unsorted() {
+    use randomize::Rng;
+
+    // Events in no particular order, some at the same time, count as if sorted.
+    let mut rng = Rng::new(5);
+    let mut events = Vec::new();
+    for _ in 0..40 {
+        let (u, v) = (rng.below(5), rng.below(5));
+        if u != v {
+            events.push((u, v, 1, rng.below(20) as Timestamp));
+        }
+    }
+    let net = temporal_network(&events);
+    let mut sorted = net.clone();
+    sorted.sort_events();
+    assert!(net.events != sorted.events);
+    for &(k, l) in &[(2, 2), (3, 2), (3, 3), (4, 3)] {
+        let counts = temporal_motifs(&net, k, l, 3).unwrap();
+        assert!(!counts.is_empty());
+        assert_eq!(temporal_motifs(&sorted, k, l, 3).unwrap(), counts);
+    }
+}
+
+#[test]
+fn test_temporal_motifs_brute_force() {
+    use randomize::Rng;
+
+    let mut rng = Rng::new(21);
+    let n = 6;
+    let mut events = Vec::new();
+    for t in 0..40 {
+        let (u, v) = (rng.below(n), rng.below(n));
+        if u != v {
+            events.push((u, v, 1 + rng.below(2) as EdgeType, t / 2));
+        }
+    }
+    let net = temporal_network(&events);
+    for &(k, l) in &[(2, 2), (3, 2), (3, 3), (4, 3), (3, 4)] {
+        let delta = 4;
+        // Every l events in order, within delta, on k connected nodes.
+        let mut instances: Vec<Vec<Event>> = Vec::new();
+        let m = net.events.len();
+        let mut chosen = Vec::from_iter(0..l);
+        loop {
+            let seq = Vec::from_iter(chosen.iter().map(|i| net.events[*i]));
+            let mut pattern = Network::new();
+            let mut nodes = HashMap::new();
+            for e in &seq {
+                let u = *nodes.entry(e.source).or_insert_with(|| pattern.add_node(String::new()));
+                let v = *nodes.entry(e.target).or_insert_with(|| pattern.add_node(String::new()));
+                pattern.add_edge(u, v, e.edge_type);
+            }
+            if seq[l - 1].time - seq[0].time <= delta && nodes.len() == k &&
+               ::petgraph::algo::connected_components(&pattern) == 1 {
+                instances.push(seq);
+            }
+            match (0..l).rev().find(|i| chosen[*i] < m - l + i) {
+                Some(i) => {
+                    chosen[i] += 1;
+                    for j in i + 1..l {
+                        chosen[j] = chosen[j - 1] + 1;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        // Sort them into classes, by trying every way to map one onto another.
+        let same = |a: &[Event], b: &[Event]| {
+            let mut map = HashMap::new();
+            let mut back = HashMap::new();
+            a.iter().zip(b).all(|(x, y)| {
+                x.edge_type == y.edge_type &&
+                [(x.source, y.source), (x.target, y.target)].iter().all(|&(u, v)| {
+                    *map.entry(u).or_insert(v) == v && *back.entry(v).or_insert(u) == u
+                })
+            })
+        };
+        let mut classes: Vec<(Vec<Event>, usize)> = Vec::new();
+        for seq in instances {
+            match classes.iter().position(|c| same(&c.0, &seq)) {
+                Some(i) => classes[i].1 += 1,
+                None => classes.push((seq, 1)),
+            }
+        }
+        let mut expected = Vec::from_iter(classes.iter().map(|c| c.1));
+        expected.sort();
+        let mut counts = Vec::from_iter(temporal_motifs(&net, k, l, delta).unwrap().values().cloned());
+        counts.sort();
+        assert!(!counts.is_empty());
+        assert_eq!(expected, counts);
+    }
+}